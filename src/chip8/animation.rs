@@ -0,0 +1,477 @@
+//! Recordings of the display as animated GIF or APNG images, a frame for every 60 Hz frame of
+//! emulated time.
+//!
+//! Frames identical to the one before are merged, showing it for longer, and only the part of a
+//! frame that changed is stored. The display switching resolution doesn't change the size of the
+//! image: the lower resolution is scaled up to the highest one recorded.
+
+use crate::chip8::{Chip8, Display, Screenshot};
+use crate::chip8::deflate::{zlib, BitWriter};
+use crate::chip8::screenshot::{png_chunk, png_scanlines};
+use std::fs;
+use std::io;
+
+/// The formats recordings are saved in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    /// Animated GIF. Its delays are in hundredths of a second, and players slow down the shortest
+    /// ones, so frames are shown for 2/100 of a second at least: one that would show for less is
+    /// replaced by the next. The recording still lasts as long as it ran.
+    Gif,
+    /// Animated PNG, showing every frame for exactly as long as it lasted.
+    Apng
+}
+impl AnimationFormat {
+    /// The format a file is in, by its extension: `.gif`, or `.png` or `.apng`.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            _ => None
+        }
+    }
+}
+
+/// A frame of the display, as recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    width: usize,
+    height: usize,
+    /// Colours of the pixels, row by row.
+    pixels: Vec<u8>,
+    /// Number of 60 Hz frames it was shown for.
+    duration: u64
+}
+impl Frame {
+    fn new(display: &Display) -> Self {
+        Self { width: display.width(), height: display.height(), pixels: display.frame().to_vec(), duration: 1 }
+    }
+
+    /// Whether the frame is what `display` shows.
+    fn shows(&self, display: &Display) -> bool {
+        (self.width, self.height) == (display.width(), display.height()) && self.pixels == display.frame()
+    }
+}
+
+/// A part of the image, such as what changed from a frame to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize
+}
+
+/// Records the display of the machine it is attached to at the end of every frame, saving the
+/// recording when finished with.
+pub struct Recorder {
+    screenshot: Screenshot,
+    file: String,
+    format: AnimationFormat,
+    frames: Vec<Frame>
+}
+impl Recorder {
+    /// Creates a recorder drawing frames with the scale and palette of `screenshot`, saving to
+    /// `file` in the format of its extension.
+    pub fn new(screenshot: Screenshot, file: &str) -> io::Result<Self> {
+        let format = AnimationFormat::from_path(file).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+            "unknown animation format, expected a .gif, .png or .apng file"))?;
+        Ok(Self { screenshot, file: file.to_string(), format, frames: Vec::new() })
+    }
+
+    /// Number of distinct frames recorded.
+    pub fn frames(&self) -> usize {
+        self.frames.len()
+    }
+    /// Number of 60 Hz frames recorded.
+    pub fn duration(&self) -> u64 {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    /// Records the display of `system` as a frame; called by the machine at the end of every frame.
+    pub fn capture(&mut self, system: &Chip8) {
+        match self.frames.last_mut() {
+            Some(last) if last.shows(&system.display) => last.duration += 1,
+            _ => self.frames.push(Frame::new(&system.display))
+        }
+    }
+
+    /// Saves the recording, ending with the display of `system` if it changed since the last frame,
+    /// e.g. as the machine stopped halfway through a frame.
+    pub fn finish(mut self, system: &Chip8) -> io::Result<()> {
+        if !self.frames.last().is_some_and(|last| last.shows(&system.display)) {
+            self.frames.push(Frame::new(&system.display));
+        }
+        fs::write(&self.file, self.encode(self.format))
+    }
+
+    /// The recording in `format`; empty if no frame was recorded.
+    pub fn encode(&self, format: AnimationFormat) -> Vec<u8> {
+        if self.frames.is_empty() {
+            return Vec::new();
+        }
+        match format {
+            AnimationFormat::Gif => self.gif(),
+            AnimationFormat::Apng => self.apng()
+        }
+    }
+
+    fn gif(&self) -> Vec<u8> {
+        let canvas = Canvas::new(&self.frames, self.screenshot.scale());
+        // The frames shown, and the hundredth of a second they start at, rounded, so that the delays
+        // add up to the time the recording lasted. A frame that would be shown for less than the
+        // shortest delay is replaced by the next, which then starts at its time.
+        let hundredths = |frames: u64| (frames * 100 + 30) / 60;
+        let mut shown: Vec<(usize, u64)> = Vec::new();
+        let mut start = 0;
+        for (i, frame) in self.frames.iter().enumerate() {
+            let time = hundredths(start);
+            match shown.last_mut() {
+                Some(last) if time < last.1 + MIN_GIF_DELAY => last.0 = i,
+                _ => shown.push((i, time))
+            }
+            start += frame.duration;
+        }
+        let end = hundredths(start);
+        if shown.len() > 1 && end < shown[shown.len() - 1].1 + MIN_GIF_DELAY {
+            let (last, _) = shown.pop().unwrap();
+            shown.last_mut().unwrap().0 = last;
+        }
+
+        let (width, height) = canvas.size();
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&(width as u16).to_le_bytes());
+        gif.extend_from_slice(&(height as u16).to_le_bytes());
+        // A global colour table of 4 colours, the first the background.
+        gif.extend_from_slice(&[0x91, 0, 0]);
+        gif.extend_from_slice(&self.screenshot.palette().concat());
+        // Loops forever.
+        gif.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+        let mut previous = None;
+        for (n, &(i, time)) in shown.iter().enumerate() {
+            let delay = shown.get(n + 1).map_or(end, |next| next.1) - time;
+            // A delay too long for a single image goes on over images of a pixel that didn't change.
+            for (part, delay) in split(delay, u16::MAX as u64).enumerate() {
+                let rect = canvas.changes(if part == 0 { previous } else { Some(i) }, i);
+                // Graphic control: left in place for the next frame to be drawn over, shown for a delay
+                // in hundredths of a second.
+                gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x04]);
+                gif.extend_from_slice(&(delay as u16).to_le_bytes());
+                gif.extend_from_slice(&[0, 0]);
+
+                let (rect, pixels) = canvas.region(i, rect);
+                gif.push(0x2C);
+                for n in [rect.x, rect.y, rect.width, rect.height] {
+                    gif.extend_from_slice(&(n as u16).to_le_bytes());
+                }
+                gif.push(0);
+                gif.push(MIN_CODE_SIZE as u8);
+                for block in lzw(&pixels).chunks(255) {
+                    gif.push(block.len() as u8);
+                    gif.extend_from_slice(block);
+                }
+                gif.push(0);
+            }
+            previous = Some(i);
+        }
+        gif.push(0x3B);
+        gif
+    }
+
+    fn apng(&self) -> Vec<u8> {
+        let canvas = Canvas::new(&self.frames, self.screenshot.scale());
+        let (width, height) = canvas.size();
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 3, 0, 0, 0]);
+        png_chunk(&mut png, b"IHDR", &header);
+        // A frame shown for longer than a single frame control allows goes on over frames of a pixel
+        // that didn't change.
+        let max = u16::MAX as u64;
+        // The number of frames, played in a loop forever.
+        let count: u64 = self.frames.iter().map(|frame| split(frame.duration, max).count() as u64).sum();
+        let mut control = (count as u32).to_be_bytes().to_vec();
+        control.extend_from_slice(&0u32.to_be_bytes());
+        png_chunk(&mut png, b"acTL", &control);
+        png_chunk(&mut png, b"PLTE", &self.screenshot.palette().concat());
+
+        // Frame control and data chunks are numbered in a single sequence.
+        let mut sequence = 0u32;
+        for (i, frame) in self.frames.iter().enumerate() {
+            for (n, duration) in split(frame.duration, max).enumerate() {
+                let rect = canvas.changes(if n == 0 { i.checked_sub(1) } else { Some(i) }, i);
+                let (rect, pixels) = canvas.region(i, rect);
+
+                let mut control = sequence.to_be_bytes().to_vec();
+                for n in [rect.width, rect.height, rect.x, rect.y] {
+                    control.extend_from_slice(&(n as u32).to_be_bytes());
+                }
+                // Shown for a number of 60ths of a second, left in place and drawn over what was there.
+                control.extend_from_slice(&(duration as u16).to_be_bytes());
+                control.extend_from_slice(&60u16.to_be_bytes());
+                control.extend_from_slice(&[0, 0]);
+                png_chunk(&mut png, b"fcTL", &control);
+                sequence += 1;
+
+                let data = zlib(&png_scanlines(&pixels, rect.width));
+                if sequence == 1 {
+                    png_chunk(&mut png, b"IDAT", &data);
+                } else {
+                    let mut chunk = sequence.to_be_bytes().to_vec();
+                    chunk.extend_from_slice(&data);
+                    png_chunk(&mut png, b"fdAT", &chunk);
+                    sequence += 1;
+                }
+            }
+        }
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+/// Splits `delay` into as few delays as it takes for none to be longer than `max`.
+fn split(delay: u64, max: u64) -> impl Iterator<Item = u64> {
+    (0..(delay + max - 1) / max).map(move |n| (delay - n * max).min(max))
+}
+
+/// The frames recorded, all scaled up to the highest resolution among them.
+struct Canvas {
+    width: usize,
+    height: usize,
+    scale: usize,
+    frames: Vec<Vec<u8>>
+}
+impl Canvas {
+    fn new(frames: &[Frame], scale: usize) -> Self {
+        let width = frames.iter().map(|frame| frame.width).max().unwrap_or(0);
+        let height = frames.iter().map(|frame| frame.height).max().unwrap_or(0);
+        let frames = frames.iter()
+            .map(|frame| {
+                let (x_scale, y_scale) = (width / frame.width, height / frame.height);
+                (0..width * height).map(|i| frame.pixels[(i / width / y_scale) * frame.width + i % width / x_scale]).collect()
+            })
+            .collect();
+        Self { width, height, scale, frames }
+    }
+
+    /// The size of the image, in pixels of the image.
+    fn size(&self) -> (usize, usize) {
+        (self.width * self.scale, self.height * self.scale)
+    }
+
+    /// The smallest rectangle holding the changes from the frame `previous`, if any, to the frame
+    /// `current`, in pixels of the display; a single pixel if nothing changed.
+    fn changes(&self, previous: Option<usize>, current: usize) -> Rect {
+        let previous = match previous {
+            Some(previous) => &self.frames[previous],
+            None => return Rect { x: 0, y: 0, width: self.width, height: self.height }
+        };
+        let changed: Vec<(usize, usize)> = previous.iter().zip(&self.frames[current]).enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(i, _)| (i % self.width, i / self.width))
+            .collect();
+        if changed.is_empty() {
+            return Rect { x: 0, y: 0, width: 1, height: 1 };
+        }
+        let (x, y) = (changed.iter().map(|c| c.0).min().unwrap(), changed[0].1);
+        let (right, bottom) = (changed.iter().map(|c| c.0).max().unwrap(), changed[changed.len() - 1].1);
+        Rect { x, y, width: right - x + 1, height: bottom - y + 1 }
+    }
+
+    /// `rect`, in pixels of the display, scaled to pixels of the image, and the colours of its
+    /// pixels in the frame `frame`.
+    fn region(&self, frame: usize, rect: Rect) -> (Rect, Vec<u8>) {
+        let frame = &self.frames[frame];
+        let (width, height) = (rect.width * self.scale, rect.height * self.scale);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| frame[(rect.y + y / self.scale) * self.width + rect.x + x / self.scale])
+            .collect();
+        (Rect { x: rect.x * self.scale, y: rect.y * self.scale, width, height }, pixels)
+    }
+}
+
+/// Shortest delay of a GIF frame, in hundredths of a second, that players show as asked; browsers
+/// show the shorter ones for 1/10 of a second.
+const MIN_GIF_DELAY: u64 = 2;
+
+/// Bits of the colours of the pixels of a GIF, at least 2 as required, for the 4 colours.
+const MIN_CODE_SIZE: u32 = 2;
+const MAX_CODE: u16 = 4095;
+
+/// Compresses the colours of the pixels of a GIF image with its variant of LZW.
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut out = BitWriter::new(Vec::new());
+    // The codes of the sequences extending a sequence, by its code, with each colour; 0 if there is
+    // none.
+    let mut table: Vec<[u16; 4]> = Vec::new();
+    let reset = |table: &mut Vec<[u16; 4]>| {
+        table.clear();
+        table.resize(end as usize + 1, [0; 4]);
+    };
+    reset(&mut table);
+    let mut size = MIN_CODE_SIZE + 1;
+    out.bits(clear as u32, size);
+
+    let mut pixels = pixels.iter().map(|color| *color as usize & 0x3);
+    let mut code = match pixels.next() {
+        Some(color) => color as u16,
+        None => {
+            out.bits(end as u32, size);
+            return out.finish();
+        }
+    };
+    for color in pixels {
+        match table[code as usize][color] {
+            0 => {
+                out.bits(code as u32, size);
+                let next = table.len() as u16;
+                if next <= MAX_CODE {
+                    table[code as usize][color] = next;
+                    table.push([0; 4]);
+                    if next == 1 << size {
+                        size += 1;
+                    }
+                } else {
+                    out.bits(clear as u32, size);
+                    reset(&mut table);
+                    size = MIN_CODE_SIZE + 1;
+                }
+                code = color as u16;
+            },
+            extended => code = extended
+        }
+    }
+    out.bits(code as u32, size);
+    out.bits(end as u32, size);
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{boot, Variant};
+
+    /// Draws a box moving a pixel to the right every 2 frames, for 3 moves.
+    fn system() -> Chip8 {
+        let mut system = boot(Variant::SuperChip, "
+            : main
+                v0 := 0
+                v1 := 0
+                i := box
+                loop
+                    sprite v0 v1 2
+                    v2 := 2
+                    delay := v2
+                    loop
+                        v2 := delay
+                        if v2 != 0 then
+                    again
+                    sprite v0 v1 2
+                    v0 += 1
+                    if v0 == 3 then exit
+                again
+            : box
+                0xC0 0xC0
+        ");
+        system.recorder = Some(Recorder::new(Screenshot::new(), "unused.gif").unwrap());
+        system
+    }
+
+    #[test]
+    fn records_every_frame_merging_identical_ones() {
+        let mut system = system();
+        for _ in 0..5 {
+            system.run_frame().unwrap();
+        }
+        let recorder = system.recorder.as_ref().unwrap();
+        assert_eq!((recorder.frames(), recorder.duration()), (3, 5));
+        assert_eq!(recorder.frames.iter().map(|frame| frame.duration).collect::<Vec<_>>(), vec![2, 2, 1]);
+
+        // The same pixels in another resolution are another frame.
+        let mut recorder = system.recorder.take().unwrap();
+        system.display.set_hires(true);
+        recorder.capture(&system);
+        assert_eq!(recorder.frames(), 4);
+    }
+
+    #[test]
+    fn saves_apng_showing_every_frame_for_as_long_as_it_lasted() {
+        let mut system = system();
+        for _ in 0..5 {
+            system.run_frame().unwrap();
+        }
+        let apng = system.recorder.unwrap().encode(AnimationFormat::Apng);
+
+        assert_eq!(&apng[16..24], b"\0\0\0\x40\0\0\0\x20");
+        assert_eq!(&apng[37..45], b"acTL\0\0\0\x03");
+        let controls: Vec<&[u8]> = apng.windows(30).filter(|chunk| chunk.starts_with(b"fcTL")).map(|chunk| &chunk[4..30]).collect();
+        // The first frame is whole, shown for 2/60 of a second; the next only the pixels that changed,
+        // from the column the box left to the one it moved into.
+        assert_eq!(controls[0], b"\0\0\0\0\0\0\0\x40\0\0\0\x20\0\0\0\0\0\0\0\0\0\x02\0\x3C\0\0");
+        assert_eq!(controls[1], b"\0\0\0\x01\0\0\0\x03\0\0\0\x02\0\0\0\0\0\0\0\0\0\x02\0\x3C\0\0");
+        // The image data of the frames after the first is numbered along with their controls.
+        assert_eq!(&controls[2][..4], b"\0\0\0\x03");
+    }
+
+    #[test]
+    fn saves_gif_with_frames_of_2_hundredths_of_a_second_at_least() {
+        let mut recorder = Recorder::new(Screenshot::new(), "unused.gif").unwrap();
+        let pixels = |color| vec![color; 64 * 32];
+        for color in [0, 1, 2, 3, 0, 1] {
+            recorder.frames.push(Frame { width: 64, height: 32, pixels: pixels(color), duration: 1 });
+        }
+        let gif = recorder.encode(AnimationFormat::Gif);
+
+        assert_eq!(&gif[..13], b"GIF89a\x40\0\x20\0\x91\0\0");
+        // The second and fifth frames would show for 1/100 of a second, and are replaced by the next;
+        // the delays still add up to the 10/100 of a second the 6 frames lasted.
+        let delays: Vec<u16> = gif.windows(6).filter(|block| block.starts_with(b"\x21\xF9\x04\x04"))
+            .map(|block| u16::from_le_bytes([block[4], block[5]])).collect();
+        assert_eq!(delays, vec![2, 3, 2, 3]);
+        assert!(delays.iter().all(|delay| *delay as u64 >= MIN_GIF_DELAY));
+        // The last frame shown is the last recorded.
+        recorder.frames.truncate(5);
+        let gif = recorder.encode(AnimationFormat::Gif);
+        let delays: Vec<u16> = gif.windows(6).filter(|block| block.starts_with(b"\x21\xF9\x04\x04"))
+            .map(|block| u16::from_le_bytes([block[4], block[5]])).collect();
+        assert_eq!(delays, vec![2, 3, 3]);
+        assert_eq!(gif.last(), Some(&0x3B));
+    }
+
+    #[test]
+    fn splits_delays_too_long_for_a_single_frame() {
+        let mut recorder = Recorder::new(Screenshot::new(), "unused.gif").unwrap();
+        for (color, duration) in [(0, 1), (1, 70000)] {
+            recorder.frames.push(Frame { width: 64, height: 32, pixels: vec![color; 64 * 32], duration });
+        }
+
+        // The second frame goes on, unchanged, over a 1 by 1 image.
+        let apng = recorder.encode(AnimationFormat::Apng);
+        assert_eq!(&apng[37..45], b"acTL\0\0\0\x03");
+        let controls: Vec<&[u8]> = apng.windows(30).filter(|chunk| chunk.starts_with(b"fcTL")).map(|chunk| &chunk[4..30]).collect();
+        let durations: Vec<u16> = controls.iter().map(|control| u16::from_be_bytes([control[20], control[21]])).collect();
+        assert_eq!(durations, vec![1, 65535, 4465]);
+        assert_eq!(&controls[2][4..12], b"\0\0\0\x01\0\0\0\x01");
+
+        let gif = recorder.encode(AnimationFormat::Gif);
+        let delays: Vec<u16> = gif.windows(6).filter(|block| block.starts_with(b"\x21\xF9\x04\x04"))
+            .map(|block| u16::from_le_bytes([block[4], block[5]])).collect();
+        assert_eq!(delays, vec![2, 65535, 51131]);
+    }
+
+    #[test]
+    fn compresses_with_lzw() {
+        // Clear, then 0, 0 0 and 1 as codes 0, 6 and 1, then 1 1 as code 8 once the codes grew to 4
+        // bits, and the end.
+        assert_eq!(lzw(&[0, 0, 0, 1, 1, 1]), [0x84, 0x83, 0x05]);
+        assert_eq!(lzw(&[]), [0x2C]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,841 @@
+use crate::chip8::{Opcode, Variant};
+use crate::chip8::bios::PROGRAM_ADDR;
+use crate::chip8::memory::MAX_MEMORY_SIZE;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Write};
+
+use self::AssemblerError::*;
+
+/// Number of macro expansions after which a macro is taken to be expanding itself forever.
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// Words that can't be used as the name of a label, constant, alias or macro.
+const KEYWORDS: &[&str] = &[
+    ":", ":alias", ":const", ":macro", ":byte", ":call", "{", "}", "clear", "return", ";", "exit",
+    "lores", "hires", "scroll-down", "scroll-left", "scroll-right", "jump", "jump0", "sprite",
+    "save", "load", "bcd", "saveflags", "loadflags", "plane", "audio", "delay", "buzzer", "pitch",
+    "i", "hex", "bighex", "long", "random", "key", "-key", "loop", "while", "again", "if", "then",
+    "begin", "else", "end", ":=", "+=", "-=", "=-", "|=", "&=", "^=", ">>=", "<<=", "==", "!=", "-"
+];
+
+/// The result of assembling a program: an image to be loaded at `origin`, and its symbols.
+pub struct Assembly {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    pub constants: BTreeMap<String, i64>
+}
+impl Assembly {
+    /// Formats the labels by address, followed by the constants.
+    pub fn symbol_table(&self) -> String {
+        let mut writer = String::new();
+
+        let mut labels: Vec<(&String, &u16)> = self.labels.iter().collect();
+        labels.sort_by_key(|(name, addr)| (**addr, name.as_str()));
+        for (name, addr) in labels {
+            writeln!(writer, "{:04X}  {}", addr, name).unwrap();
+        }
+        for (name, value) in &self.constants {
+            writeln!(writer, "{:>4}  {} (const)", value, name).unwrap();
+        }
+
+        writer
+    }
+}
+
+/// Assembles programs written in the syntax of [Octo](https://github.com/JohnEarnest/Octo).
+///
+/// Supported are labels (`: name`), the statements of every instruction (`v0 := 5`, `i := label`,
+/// `sprite v0 v1 5`, ...), bare numbers as data bytes, `:alias`, `:const`, `:macro`, `:byte`,
+/// `:call`, `loop` ... `while` ... `again`, and `if ... then` and `if ... begin ... else ... end`
+/// over `==`, `!=`, `key` and `-key`.
+///
+/// As with Octo, if a `main` label is defined anywhere but before the first byte or label of the
+/// program, the program starts with a jump to it.
+pub struct Assembler {
+    variant: Variant,
+    origin: u16
+}
+impl Assembler {
+    pub fn new(variant: Variant) -> Self {
+        Self {
+            variant,
+            origin: PROGRAM_ADDR
+        }
+    }
+
+    pub fn assemble(&self, source: &str) -> Result<Assembly, AssemblerError> {
+        let tokens = tokenize(source);
+        let defines_main = tokens.iter().zip(tokens.iter().skip(1))
+            .any(|(colon, name)| colon.text == ":" && name.text == "main");
+
+        let mut context = Context {
+            variant: self.variant,
+            origin: self.origin,
+            tokens,
+            line: 1,
+            bytes: Vec::new(),
+            labels: BTreeMap::new(),
+            constants: BTreeMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+            jump_to_main: defines_main
+        };
+
+        while let Some(token) = context.tokens.pop_front() {
+            context.line = token.line;
+            context.statement(token)?;
+        }
+        context.finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssemblerError {
+    SyntaxError { line: usize, message: String },
+    UndefinedSymbolError { line: usize, name: String },
+    RedefinedSymbolError { line: usize, name: String },
+    ValueRangeError { line: usize, value: i64, min: i64, max: i64 },
+    UnsupportedInstructionError { line: usize, operation: Opcode },
+    ProgramTooLargeError { line: usize }
+}
+impl AssemblerError {
+    /// The source line the error was found on, counting from 1.
+    pub fn line(&self) -> usize {
+        match *self {
+            SyntaxError { line, .. } | UndefinedSymbolError { line, .. } | RedefinedSymbolError { line, .. } |
+            ValueRangeError { line, .. } | UnsupportedInstructionError { line, .. } | ProgramTooLargeError { line } => line
+        }
+    }
+}
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line())?;
+        match self {
+            SyntaxError { message, .. } => write!(f, "{}", message),
+            UndefinedSymbolError { name, .. } => write!(f, "undefined symbol `{}`", name),
+            RedefinedSymbolError { name, .. } => write!(f, "symbol `{}` is already defined", name),
+            ValueRangeError { value, min, max, .. } => write!(f, "value {} is out of the range {} to {}", value, min, max),
+            UnsupportedInstructionError { operation, .. } => write!(f, "`{}` is not supported by the variant", operation),
+            ProgramTooLargeError { .. } => write!(f, "program exceeds the {} bytes of addressable memory", MAX_MEMORY_SIZE)
+        }
+    }
+}
+impl Error for AssemblerError { }
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    source.lines().enumerate()
+        .flat_map(|(i, line)| {
+            let code = line.split('#').next().unwrap_or("");
+            code.split_whitespace().map(move |text| Token { text: text.to_string(), line: i + 1 })
+        })
+        .collect()
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text)
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    match text.strip_prefix('v').or_else(|| text.strip_prefix('V')) {
+        Some(digit) if digit.len() == 1 => u8::from_str_radix(digit, 16).ok(),
+        _ => None
+    }
+}
+
+/// The operand field a symbol is patched into once it is defined.
+#[derive(Clone, Copy)]
+enum Field {
+    /// The low 12 bits of an instruction word.
+    Address,
+    /// A whole word, following `LD I, long addr`.
+    Long
+}
+impl Field {
+    fn max(self) -> i64 {
+        match self {
+            Field::Address => 0xFFF,
+            Field::Long => 0xFFFF
+        }
+    }
+}
+
+/// A reference to a symbol that was not yet defined when it was used.
+struct Fixup {
+    offset: usize,
+    field: Field,
+    name: String,
+    line: usize
+}
+
+#[derive(Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>
+}
+
+enum Block {
+    /// A `loop`, with the jumps out of it of its `while`s.
+    Loop { start: u16, breaks: Vec<usize> },
+    /// An `if ... begin` or `else`, with the jump past it.
+    If { jump: usize }
+}
+
+/// A condition, as the instructions that skip the next instruction when it holds, and when not.
+struct Condition {
+    skip_if_true: Opcode,
+    skip_if_false: Opcode
+}
+
+struct Context {
+    variant: Variant,
+    origin: u16,
+    tokens: VecDeque<Token>,
+    /// The line of the last token taken.
+    line: usize,
+    bytes: Vec<u8>,
+    labels: BTreeMap<String, u16>,
+    constants: BTreeMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(usize, Block)>,
+    expansions: usize,
+    /// Whether the program is still to start with a jump to `main`.
+    jump_to_main: bool
+}
+impl Context {
+    fn next(&mut self) -> Result<Token, AssemblerError> {
+        let token = self.tokens.pop_front().ok_or_else(|| SyntaxError { line: self.line, message: "unexpected end of input".to_string() })?;
+        self.line = token.line;
+        Ok(token)
+    }
+    fn expect(&mut self, text: &str) -> Result<(), AssemblerError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(syntax(&token, format!("expected `{}`, found `{}`", text, token.text)));
+        }
+        Ok(())
+    }
+
+    fn here(&self) -> usize {
+        self.origin as usize + self.bytes.len()
+    }
+
+    /// Starts the program with the jump to `main`, unless it is defined right at the start.
+    fn start(&mut self) -> Result<(), AssemblerError> {
+        if !self.jump_to_main {
+            return Ok(());
+        }
+        self.jump_to_main = false;
+        let main = Token { text: "main".to_string(), line: self.line };
+        self.address_op(&main, Field::Address, |n| Opcode::_1nnn { n })
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), AssemblerError> {
+        self.start()?;
+        if self.here() + bytes.len() > MAX_MEMORY_SIZE {
+            return Err(ProgramTooLargeError { line: self.line });
+        }
+        self.bytes.extend_from_slice(bytes);
+        Ok(())
+    }
+    fn op(&mut self, operation: Opcode) -> Result<(), AssemblerError> {
+        if !self.variant.supports(&operation) {
+            return Err(UnsupportedInstructionError { line: self.line, operation });
+        }
+        self.emit(&operation.encode_bytes())
+    }
+    /// Emits the instruction built from the address `token`, to be patched in later if the
+    /// address is a label that is not yet defined.
+    fn address_op<F: Fn(u16) -> Opcode>(&mut self, token: &Token, field: Field, build: F) -> Result<(), AssemblerError> {
+        // The jump to `main` goes first, or the offset of the fixup would be that of the jump.
+        self.start()?;
+        let addr = match self.resolve(token)? {
+            Some(value) => range(token, value, 0, field.max())? as u16,
+            None => {
+                let offset = match field {
+                    Field::Address => self.bytes.len(),
+                    Field::Long => self.bytes.len() + 2
+                };
+                self.fixups.push(Fixup { offset, field, name: token.text.clone(), line: token.line });
+                0
+            }
+        };
+        self.op(build(addr))
+    }
+    /// Emits a jump to be patched by `patch_jump`, returning its offset.
+    fn jump(&mut self) -> Result<usize, AssemblerError> {
+        self.start()?;
+        let offset = self.bytes.len();
+        self.op(Opcode::_1nnn { n: 0 })?;
+        Ok(offset)
+    }
+    /// Points the jump at `offset` to the current address.
+    fn patch_jump(&mut self, offset: usize) -> Result<(), AssemblerError> {
+        let target = self.here() as i64;
+        let line = self.line;
+        range(&Token { text: String::new(), line }, target, 0, Field::Address.max())?;
+        self.patch(offset, Field::Address, target as u16);
+        Ok(())
+    }
+    fn patch(&mut self, offset: usize, field: Field, value: u16) {
+        match field {
+            Field::Address => {
+                self.bytes[offset] = (self.bytes[offset] & 0xF0) | (value >> 8) as u8;
+                self.bytes[offset + 1] = value as u8;
+            },
+            Field::Long => self.bytes[offset..(offset + 2)].copy_from_slice(&value.to_be_bytes())
+        }
+    }
+
+    /// Takes a name for a new symbol.
+    fn name(&mut self) -> Result<Token, AssemblerError> {
+        let token = self.next()?;
+        if KEYWORDS.contains(&token.text.as_str()) || parse_number(&token.text).is_some() || parse_register(&token.text).is_some() {
+            return Err(syntax(&token, format!("`{}` can't be used as a name", token.text)));
+        }
+        if self.labels.contains_key(&token.text) || self.constants.contains_key(&token.text) ||
+           self.aliases.contains_key(&token.text) || self.macros.contains_key(&token.text) {
+            return Err(RedefinedSymbolError { line: token.line, name: token.text });
+        }
+        Ok(token)
+    }
+
+    /// The value of a number, constant or label, or `None` for a name that is not yet defined.
+    fn resolve(&self, token: &Token) -> Result<Option<i64>, AssemblerError> {
+        if let Some(value) = parse_number(&token.text) {
+            return Ok(Some(value));
+        }
+        if let Some(value) = self.constants.get(&token.text) {
+            return Ok(Some(*value));
+        }
+        if let Some(addr) = self.labels.get(&token.text) {
+            return Ok(Some(*addr as i64));
+        }
+        if KEYWORDS.contains(&token.text.as_str()) || self.register(token).is_some() {
+            return Err(syntax(token, format!("expected a value, found `{}`", token.text)));
+        }
+        Ok(None)
+    }
+    fn value(&mut self, min: i64, max: i64) -> Result<i64, AssemblerError> {
+        let token = self.next()?;
+        match self.resolve(&token)? {
+            Some(value) => range(&token, value, min, max),
+            None => Err(UndefinedSymbolError { line: token.line, name: token.text })
+        }
+    }
+    fn byte(&mut self) -> Result<u8, AssemblerError> {
+        self.value(-128, 0xFF).map(|value| value as u8)
+    }
+    fn nibble(&mut self) -> Result<u8, AssemblerError> {
+        self.value(0, 0xF).map(|value| value as u8)
+    }
+
+    fn register(&self, token: &Token) -> Option<u8> {
+        parse_register(&token.text).or_else(|| self.aliases.get(&token.text).copied())
+    }
+    fn expect_register(&mut self) -> Result<u8, AssemblerError> {
+        let token = self.next()?;
+        self.register(&token).ok_or_else(|| syntax(&token, format!("expected a register, found `{}`", token.text)))
+    }
+
+    fn condition(&mut self) -> Result<Condition, AssemblerError> {
+        let x = self.expect_register()?;
+        let operator = self.next()?;
+        let (equal, not_equal) = match operator.text.as_str() {
+            "key" => return Ok(Condition { skip_if_true: Opcode::_Ex9E { x }, skip_if_false: Opcode::_ExA1 { x } }),
+            "-key" => return Ok(Condition { skip_if_true: Opcode::_ExA1 { x }, skip_if_false: Opcode::_Ex9E { x } }),
+            "==" | "!=" => {
+                let rhs = self.next()?;
+                match self.register(&rhs) {
+                    Some(y) => (Opcode::_5xy0 { x, y }, Opcode::_9xy0 { x, y }),
+                    None => {
+                        self.tokens.push_front(rhs);
+                        let k = self.byte()?;
+                        (Opcode::_3xkk { x, k }, Opcode::_4xkk { x, k })
+                    }
+                }
+            },
+            _ => return Err(syntax(&operator, format!("expected `==`, `!=`, `key` or `-key`, found `{}`", operator.text)))
+        };
+
+        Ok(match operator.text.as_str() {
+            "==" => Condition { skip_if_true: equal, skip_if_false: not_equal },
+            _ => Condition { skip_if_true: not_equal, skip_if_false: equal }
+        })
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AssemblerError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                if name.text == "main" {
+                    self.jump_to_main = false;
+                }
+                self.start()?;
+                let addr = self.here();
+                if addr >= MAX_MEMORY_SIZE {
+                    return Err(ProgramTooLargeError { line: name.line });
+                }
+                self.labels.insert(name.text, addr as u16);
+            },
+            ":alias" => {
+                let name = self.name()?;
+                let x = self.expect_register()?;
+                self.aliases.insert(name.text, x);
+            },
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value(i64::from(i16::MIN), 0xFFFF)?;
+                self.constants.insert(name.text, value);
+            },
+            ":macro" => {
+                let name = self.name()?;
+                let mut params = Vec::new();
+                loop {
+                    let param = self.next()?;
+                    if param.text == "{" {
+                        break;
+                    }
+                    params.push(param.text);
+                }
+
+                let mut body = Vec::new();
+                let mut depth = 1;
+                loop {
+                    let token = self.next()?;
+                    match token.text.as_str() {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ => { }
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    body.push(token);
+                }
+                self.macros.insert(name.text, Macro { params, body });
+            },
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit(&[byte])?;
+            },
+            ":call" => {
+                let addr = self.next()?;
+                self.address_op(&addr, Field::Address, |n| Opcode::_2nnn { n })?;
+            },
+
+            "clear" => self.op(Opcode::_00E0)?,
+            "return" | ";" => self.op(Opcode::_00EE)?,
+            "exit" => self.op(Opcode::_00FD)?,
+            "lores" => self.op(Opcode::_00FE)?,
+            "hires" => self.op(Opcode::_00FF)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.op(Opcode::_00Cn { n })?;
+            },
+            "scroll-left" => self.op(Opcode::_00FC)?,
+            "scroll-right" => self.op(Opcode::_00FB)?,
+            "jump" => {
+                let addr = self.next()?;
+                self.address_op(&addr, Field::Address, |n| Opcode::_1nnn { n })?;
+            },
+            "jump0" => {
+                let addr = self.next()?;
+                self.address_op(&addr, Field::Address, |n| Opcode::_Bnnn { n })?;
+            },
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.nibble()?;
+                self.op(Opcode::_Dxyn { x, y, n })?;
+            },
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                let range = self.tokens.front().is_some_and(|token| token.text == "-");
+                let operation = if range {
+                    self.next()?;
+                    let y = self.expect_register()?;
+                    if token.text == "save" { Opcode::_5xy2 { x, y } } else { Opcode::_5xy3 { x, y } }
+                } else if token.text == "save" {
+                    Opcode::_Fx55 { x }
+                } else {
+                    Opcode::_Fx65 { x }
+                };
+                self.op(operation)?;
+            },
+            "bcd" => {
+                let x = self.expect_register()?;
+                self.op(Opcode::_Fx33 { x })?;
+            },
+            "saveflags" => {
+                let x = self.expect_register()?;
+                self.op(Opcode::_Fx75 { x })?;
+            },
+            "loadflags" => {
+                let x = self.expect_register()?;
+                self.op(Opcode::_Fx85 { x })?;
+            },
+            "plane" => {
+                let n = self.nibble()?;
+                self.op(Opcode::_Fn01 { n })?;
+            },
+            "audio" => self.op(Opcode::_F002)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                self.op(match token.text.as_str() {
+                    "delay" => Opcode::_Fx15 { x },
+                    "buzzer" => Opcode::_Fx18 { x },
+                    _ => Opcode::_Fx3A { x }
+                })?;
+            },
+            "i" => self.index_statement()?,
+
+            "loop" => {
+                self.start()?;
+                let start = self.here() as u16;
+                self.blocks.push((token.line, Block::Loop { start, breaks: Vec::new() }));
+            },
+            "while" => {
+                let condition = self.condition()?;
+                self.op(condition.skip_if_true)?;
+                let jump = self.jump()?;
+                match self.blocks.iter_mut().rev().find_map(|(_, block)| match block {
+                    Block::Loop { breaks, .. } => Some(breaks),
+                    _ => None
+                }) {
+                    Some(breaks) => breaks.push(jump),
+                    None => return Err(syntax(&token, "`while` outside of a `loop`".to_string()))
+                }
+            },
+            "again" => match self.blocks.pop() {
+                Some((_, Block::Loop { start, breaks })) => {
+                    let start = range(&token, start as i64, 0, Field::Address.max())? as u16;
+                    self.op(Opcode::_1nnn { n: start })?;
+                    for jump in breaks {
+                        self.patch_jump(jump)?;
+                    }
+                },
+                _ => return Err(syntax(&token, "`again` without a `loop`".to_string()))
+            },
+            "if" => {
+                let condition = self.condition()?;
+                let then = self.next()?;
+                match then.text.as_str() {
+                    "then" => self.op(condition.skip_if_false)?,
+                    "begin" => {
+                        self.op(condition.skip_if_true)?;
+                        let jump = self.jump()?;
+                        self.blocks.push((token.line, Block::If { jump }));
+                    },
+                    _ => return Err(syntax(&then, format!("expected `then` or `begin`, found `{}`", then.text)))
+                }
+            },
+            "else" => match self.blocks.pop() {
+                Some((line, Block::If { jump })) => {
+                    let past_else = self.jump()?;
+                    self.patch_jump(jump)?;
+                    self.blocks.push((line, Block::If { jump: past_else }));
+                },
+                _ => return Err(syntax(&token, "`else` without an `if ... begin`".to_string()))
+            },
+            "end" => match self.blocks.pop() {
+                Some((_, Block::If { jump })) => self.patch_jump(jump)?,
+                _ => return Err(syntax(&token, "`end` without an `if ... begin`".to_string()))
+            },
+
+            _ => {
+                if let Some(x) = self.register(&token) {
+                    return self.register_statement(x);
+                }
+                if let Some(value) = parse_number(&token.text) {
+                    let byte = range(&token, value, -128, 0xFF)? as u8;
+                    return self.emit(&[byte]);
+                }
+                if let Some(definition) = self.macros.get(&token.text).cloned() {
+                    return self.expand(&token, definition);
+                }
+                if KEYWORDS.contains(&token.text.as_str()) {
+                    return Err(syntax(&token, format!("unexpected `{}`", token.text)));
+                }
+                // Any other name is a call to the subroutine of that label.
+                self.address_op(&token, Field::Address, |n| Opcode::_2nnn { n })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn index_statement(&mut self) -> Result<(), AssemblerError> {
+        let operator = self.next()?;
+        match operator.text.as_str() {
+            ":=" => {
+                let rhs = self.next()?;
+                match rhs.text.as_str() {
+                    "hex" => {
+                        let x = self.expect_register()?;
+                        self.op(Opcode::_Fx29 { x })
+                    },
+                    "bighex" => {
+                        let x = self.expect_register()?;
+                        self.op(Opcode::_Fx30 { x })
+                    },
+                    "long" => {
+                        let addr = self.next()?;
+                        self.address_op(&addr, Field::Long, |n| Opcode::_F000 { n })
+                    },
+                    _ => self.address_op(&rhs, Field::Address, |n| Opcode::_Annn { n })
+                }
+            },
+            "+=" => {
+                let x = self.expect_register()?;
+                self.op(Opcode::_Fx1E { x })
+            },
+            _ => Err(syntax(&operator, format!("expected `:=` or `+=`, found `{}`", operator.text)))
+        }
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), AssemblerError> {
+        let operator = self.next()?;
+        let rhs = self.next()?;
+        let y = self.register(&rhs);
+
+        let operation = match (operator.text.as_str(), y) {
+            (":=", _) if rhs.text == "random" => Opcode::_Cxkk { x, k: self.byte()? },
+            (":=", _) if rhs.text == "delay" => Opcode::_Fx07 { x },
+            (":=", _) if rhs.text == "key" => Opcode::_Fx0A { x },
+            (":=", Some(y)) => Opcode::_8xy0 { x, y },
+            ("+=", Some(y)) => Opcode::_8xy4 { x, y },
+            ("-=", Some(y)) => Opcode::_8xy5 { x, y },
+            ("=-", Some(y)) => Opcode::_8xy7 { x, y },
+            ("|=", Some(y)) => Opcode::_8xy1 { x, y },
+            ("&=", Some(y)) => Opcode::_8xy2 { x, y },
+            ("^=", Some(y)) => Opcode::_8xy3 { x, y },
+            (">>=", Some(y)) => Opcode::_8xy6 { x, y },
+            ("<<=", Some(y)) => Opcode::_8xyE { x, y },
+            (":=", None) | ("+=", None) | ("-=", None) => {
+                self.tokens.push_front(rhs);
+                let k = self.byte()?;
+                match operator.text.as_str() {
+                    ":=" => Opcode::_6xkk { x, k },
+                    "+=" => Opcode::_7xkk { x, k },
+                    _ => Opcode::_7xkk { x, k: k.wrapping_neg() }
+                }
+            },
+            (_, None) if ["=-", "|=", "&=", "^=", ">>=", "<<="].contains(&operator.text.as_str()) => {
+                return Err(syntax(&rhs, format!("expected a register, found `{}`", rhs.text)));
+            },
+            _ => return Err(syntax(&operator, format!("unknown operator `{}`", operator.text)))
+        };
+        self.op(operation)
+    }
+
+    fn expand(&mut self, token: &Token, definition: Macro) -> Result<(), AssemblerError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(syntax(token, format!("macro `{}` expands itself without end", token.text)));
+        }
+
+        let mut args = HashMap::new();
+        for param in &definition.params {
+            let arg = self.next()?;
+            args.insert(param.as_str(), arg.text);
+        }
+        for body in definition.body.iter().rev() {
+            let text = args.get(body.text.as_str()).cloned().unwrap_or_else(|| body.text.clone());
+            self.tokens.push_front(Token { text, line: body.line });
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Assembly, AssemblerError> {
+        if let Some((line, block)) = self.blocks.pop() {
+            let message = match block {
+                Block::Loop { .. } => "`loop` without `again`",
+                Block::If { .. } => "`if ... begin` without `end`"
+            };
+            return Err(SyntaxError { line, message: message.to_string() });
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let token = Token { text: fixup.name, line: fixup.line };
+            let addr = match self.labels.get(&token.text).map(|addr| *addr as i64).or_else(|| self.constants.get(&token.text).copied()) {
+                Some(addr) => range(&token, addr, 0, fixup.field.max())? as u16,
+                None => return Err(UndefinedSymbolError { line: token.line, name: token.text })
+            };
+            self.patch(fixup.offset, fixup.field, addr);
+        }
+
+        Ok(Assembly {
+            origin: self.origin,
+            bytes: self.bytes,
+            labels: self.labels,
+            constants: self.constants
+        })
+    }
+}
+
+fn syntax(token: &Token, message: String) -> AssemblerError {
+    SyntaxError { line: token.line, message }
+}
+
+fn range(token: &Token, value: i64, min: i64, max: i64) -> Result<i64, AssemblerError> {
+    if value < min || value > max {
+        return Err(ValueRangeError { line: token.line, value, min, max });
+    }
+    Ok(value)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(source: &str) -> Result<Assembly, AssemblerError> {
+        Assembler::new(Variant::XoChip).assemble(source)
+    }
+
+    #[test]
+    fn assembles_statements() {
+        let assembly = assemble("
+            clear
+            v0 := 5  v1 := v0  v2 := random 0x0F  v3 := key
+            v0 += 1  v0 -= 1  v0 += v1  v0 -= v1  v0 =- v1
+            v0 |= v1  v0 &= v1  v0 ^= v1  v0 >>= v1  v0 <<= v1
+            i := 0x300  i += v2  i := hex v0  i := long 0x1234
+            sprite v0 v1 5  bcd v3  save v3  load v3  save v1 - v2
+            delay := v0  buzzer := v1  v4 := delay
+            return
+        ").unwrap();
+
+        assert_eq!(assembly.bytes, vec![
+            0x00, 0xE0,
+            0x60, 0x05, 0x81, 0x00, 0xC2, 0x0F, 0xF3, 0x0A,
+            0x70, 0x01, 0x70, 0xFF, 0x80, 0x14, 0x80, 0x15, 0x80, 0x17,
+            0x80, 0x11, 0x80, 0x12, 0x80, 0x13, 0x80, 0x16, 0x80, 0x1E,
+            0xA3, 0x00, 0xF2, 0x1E, 0xF0, 0x29, 0xF0, 0x00, 0x12, 0x34,
+            0xD0, 0x15, 0xF3, 0x33, 0xF3, 0x55, 0xF3, 0x65, 0x51, 0x22,
+            0xF0, 0x15, 0xF1, 0x18, 0xF4, 0x07,
+            0x00, 0xEE
+        ]);
+    }
+
+    #[test]
+    fn resolves_labels_and_jumps_to_main() {
+        let assembly = assemble("
+            : smile  0b11110000 0x90 -1
+            : main
+                i := smile
+                draw
+                jump main
+            : draw
+                sprite v0 v0 3 ;
+        ").unwrap();
+
+        assert_eq!(assembly.bytes, vec![
+            0x12, 0x05,
+            0xF0, 0x90, 0xFF,
+            0xA2, 0x02, 0x22, 0x0B, 0x12, 0x05,
+            0xD0, 0x03, 0x00, 0xEE
+        ]);
+        assert_eq!(assembly.labels["main"], 0x205);
+        assert_eq!(assembly.labels["draw"], 0x20B);
+    }
+
+    #[test]
+    fn assembles_control_flow() {
+        let assembly = assemble("
+            : main
+                loop
+                    v0 += 1
+                    while v0 != 10
+                    if v1 key then v2 := 1
+                    if v0 == v1 begin
+                        v3 := 1
+                    else
+                        v3 := 2
+                    end
+                again
+        ").unwrap();
+
+        assert_eq!(assembly.bytes, vec![
+            0x70, 0x01,
+            0x40, 0x0A, 0x12, 0x16,
+            0xE1, 0xA1, 0x62, 0x01,
+            0x50, 0x10, 0x12, 0x12, 0x63, 0x01, 0x12, 0x14, 0x63, 0x02,
+            0x12, 0x00
+        ]);
+    }
+
+    #[test]
+    fn expands_macros_constants_and_aliases() {
+        let assembly = assemble("
+            :const SPEED 3
+            :alias x v5
+            :macro move reg amount { reg += amount }
+            : main
+                move x SPEED
+                move v1 -1
+        ").unwrap();
+
+        assert_eq!(assembly.bytes, vec![0x75, 0x03, 0x71, 0xFF]);
+        assert_eq!(assembly.constants["SPEED"], 3);
+        assert_eq!(assembly.symbol_table(), "0200  main\n   3  SPEED (const)\n");
+    }
+
+    #[test]
+    fn reports_lines_of_errors() {
+        assert_eq!(assemble(": main\n  v0 := 256").err(),
+                   Some(ValueRangeError { line: 2, value: 256, min: -128, max: 0xFF }));
+        assert_eq!(assemble(": main\n\n  jump nowhere").err(),
+                   Some(UndefinedSymbolError { line: 3, name: "nowhere".to_string() }));
+        assert_eq!(assemble(": main\n: main").err(),
+                   Some(RedefinedSymbolError { line: 2, name: "main".to_string() }));
+        assert_eq!(assemble("loop\n  v0 += 1").err().map(|err| err.line()), Some(1));
+        assert_eq!(assemble("v0 := 1  # comment\nv0 ~ 1").err().map(|err| err.to_string()),
+                   Some("line 2: unknown operator `~`".to_string()));
+        assert_eq!(Assembler::new(Variant::Chip8).assemble("hires").err(),
+                   Some(UnsupportedInstructionError { line: 1, operation: Opcode::_00FF }));
+    }
+
+    #[test]
+    fn rejects_loops_out_of_the_reach_of_jumps() {
+        // Data up to 0x1000, which `JP` can't jump back to.
+        let padding = "0 ".repeat(0xE00);
+        assert_eq!(assemble(&format!("{}\nloop\n  v0 += 1\nagain", padding)).err(),
+                   Some(ValueRangeError { line: 4, value: 0x1000, min: 0, max: 0xFFF }));
+        assert!(assemble(&format!("{}\nloop\n  v0 += 1\nagain", &padding[2..])).is_ok());
+    }
+
+    #[test]
+    fn jumps_to_main_ahead_of_code_before_it() {
+        assert_eq!(assemble("jump foo\n: foo v0 := 1\n: main jump foo").unwrap().bytes,
+                   vec![0x12, 0x06, 0x12, 0x04, 0x60, 0x01, 0x12, 0x04]);
+        assert_eq!(assemble("loop v0 += 1 again\n: main jump main").unwrap().bytes,
+                   vec![0x12, 0x06, 0x70, 0x01, 0x12, 0x02, 0x12, 0x06]);
+    }
+}
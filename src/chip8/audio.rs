@@ -0,0 +1,56 @@
+use crate::chip8::snapshot::{SectionReader, SectionWriter, SnapshotError};
+
+/// Length of the XO-CHIP audio pattern buffer in bytes.
+pub const PATTERN_SIZE: usize = 16;
+/// Pitch at which the pattern plays back at 4000 bits per second.
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// The XO-CHIP audio pattern buffer and pitch register.
+///
+/// While the sound timer is running, the 128 bits of the pattern are played back in a loop as a
+/// 1-bit waveform.
+pub struct Audio {
+    pattern: [u8; PATTERN_SIZE],
+    pitch: u8
+}
+impl Audio {
+    pub fn new() -> Self {
+        Self {
+            // A square wave, for programs that never load a pattern.
+            pattern: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            pitch: DEFAULT_PITCH
+        }
+    }
+
+    pub fn pattern(&self) -> &[u8; PATTERN_SIZE] {
+        &self.pattern
+    }
+    pub fn set_pattern(&mut self, pattern: &[u8]) {
+        self.pattern.copy_from_slice(&pattern[..PATTERN_SIZE]);
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+
+    /// Playback rate of the pattern in bits per second: 4000 * 2^((*pitch* - 64) / 48).
+    pub fn sample_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - DEFAULT_PITCH as f64) / 48.0)
+    }
+
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.bytes(&self.pattern);
+        out.u8(self.pitch);
+    }
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, SnapshotError> {
+        Ok(Self { pattern: input.array()?, pitch: input.u8()? })
+    }
+}
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
\ No newline at end of file
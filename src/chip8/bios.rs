@@ -1,9 +1,21 @@
 use crate::chip8::{Memory};
-use std::io::{BufReader, Read, Error};
+use crate::chip8::memory::MemoryError;
+use crate::chip8::processor::ProcessorError;
+use log::debug;
+use std::io::{BufReader, Read};
+use std::error::Error;
 use std::fs::File;
 
+/// Address of the built-in 4x5 pixel font set.
+pub const FONT_ADDR: u16 = 0x50;
+/// Address of the built-in 8x10 pixel SUPER-CHIP font set.
+pub const LARGE_FONT_ADDR: u16 = 0xA0;
+/// Address programs are loaded at and start executing from.
+pub const PROGRAM_ADDR: u16 = 0x200;
+
 pub struct Bios {
-    fontset: [u8; 80]
+    fontset: [u8; 80],
+    large_fontset: [u8; 160]
 }
 impl Bios {
     pub fn new() -> Self {
@@ -25,21 +37,52 @@ impl Bios {
                 0xE0, 0x90, 0x90, 0x90, 0xE0, // D
                 0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
                 0xF0, 0x80, 0xF0, 0x80, 0x80  // F
+            ],
+            large_fontset: [
+                0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+                0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+                0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+                0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+                0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+                0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+                0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+                0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+                0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+                0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+                0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+                0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+                0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+                0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+                0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+                0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
             ]
         }
     }
 
-    pub fn load(&self, memory: &mut Memory) {
-        memory.copy(0x50, &self.fontset);
+    pub fn load(&self, memory: &mut Memory) -> Result<(), MemoryError> {
+        memory.copy(FONT_ADDR, &self.fontset)?;
+        memory.copy(LARGE_FONT_ADDR, &self.large_fontset)
     }
 
-    pub fn load_rom_file(&self, memory: &mut Memory, file: &str) -> Result<(), Error> {
+    pub fn load_rom_file(&self, memory: &mut Memory, file: &str) -> Result<(), Box<dyn Error>> {
         let mut buffer: Vec<u8> = Vec::new();
         BufReader::new(File::open(file)?).read_to_end(&mut buffer)?;
-        self.load_rom(memory, &buffer);
+        self.load_rom(memory, &buffer)?;
+        Ok(())
+    }
+    pub fn load_rom(&self, memory: &mut Memory, buffer: &[u8]) -> Result<(), ProcessorError> {
+        let capacity = memory.size().saturating_sub(PROGRAM_ADDR as usize);
+        if buffer.len() > capacity {
+            return Err(ProcessorError::RomTooLargeError { size: buffer.len(), capacity });
+        }
+
+        memory.copy(PROGRAM_ADDR, buffer)?;
+        debug!(target: "emul8::mem", "loaded a ROM of {} bytes at {:#05X}", buffer.len(), PROGRAM_ADDR);
         Ok(())
     }
-    pub fn load_rom(&self, memory: &mut Memory, buffer: &[u8]) {
-        memory.copy(0x200, buffer);
+}
+impl Default for Bios {
+    fn default() -> Self {
+        Self::new()
     }
 }
\ No newline at end of file
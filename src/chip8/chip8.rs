@@ -1,29 +1,245 @@
-use crate::chip8::{Processor, Memory, Bios};
+use crate::chip8::{Processor, Registers, Memory, Bios, Display, Keypad, Timers, Rng, SplitMix64, Quirks, Variant, RplFlags, Audio, Tracer, Camera, Recorder};
+use crate::chip8::headless::{self, Report, Script};
+use crate::chip8::memory::MemoryError;
+use crate::chip8::processor::ProcessorError;
+use crate::chip8::snapshot::{Snapshot, SnapshotError, SnapshotWriter};
+use log::{debug, error};
+use std::error::Error;
+use std::fs;
+
+/// Tags of the sections of a save state.
+const MACHINE_SECTION: [u8; 4] = *b"MACH";
+const PROCESSOR_SECTION: [u8; 4] = *b"CPU ";
+const MEMORY_SECTION: [u8; 4] = *b"MEM ";
+const DISPLAY_SECTION: [u8; 4] = *b"DISP";
+const KEYPAD_SECTION: [u8; 4] = *b"KEYS";
+const AUDIO_SECTION: [u8; 4] = *b"AUDI";
+const TIMERS_SECTION: [u8; 4] = *b"TIME";
+const RNG_SECTION: [u8; 4] = *b"RNG ";
+const FLAGS_SECTION: [u8; 4] = *b"FLAG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    /// Stopped by the host; resumable at any time.
+    Halted,
+    /// Stopped by the program itself (SUPER-CHIP `EXIT`).
+    Exited,
+    /// Stopped on an emulator fault. The machine is left as it was before the faulting
+    /// instruction, so it can be inspected, patched up and resumed.
+    Faulted(ProcessorError)
+}
 
 pub struct Chip8 {
     pub processor: Processor,
     pub memory: Memory,
-    pub bios: Bios
+    pub bios: Bios,
+    pub display: Display,
+    pub keypad: Keypad,
+    pub audio: Audio,
+    pub timers: Timers,
+    pub rng: Box<dyn Rng>,
+    pub rpl: RplFlags,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    /// Traces the instructions executed, if set; not part of the state of the machine.
+    pub tracer: Option<Tracer>,
+    /// Takes a screenshot at the end of a frame, if set; not part of the state of the machine.
+    pub camera: Option<Camera>,
+    /// Records every frame, if set; not part of the state of the machine.
+    pub recorder: Option<Recorder>,
+    state: RunState
 }
 impl Chip8 {
     pub fn new() -> Self {
+        Self::with_quirks(Quirks::default())
+    }
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        Self::with_variant(Variant::Chip8, quirks)
+    }
+    pub fn with_variant(variant: Variant, quirks: Quirks) -> Self {
         Self {
             processor: Processor::new(),
-            memory: Memory::new(),
-            bios: Bios::new()
+            memory: Memory::with_size(variant.memory_size()),
+            bios: Bios::new(),
+            display: Display::new(),
+            keypad: Keypad::new(),
+            audio: Audio::new(),
+            timers: Timers::new(),
+            rng: Box::new(SplitMix64::from_entropy()),
+            rpl: RplFlags::new(),
+            variant,
+            quirks,
+            tracer: None,
+            camera: None,
+            recorder: None,
+            state: RunState::Running
         }
     }
 
-    pub fn init(&mut self) {
-        self.bios.load(&mut self.memory);
+    /// Replaces the random number source with a deterministic one started from `seed`.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Box::new(SplitMix64::new(seed));
+    }
+
+    pub fn init(&mut self) -> Result<(), MemoryError> {
+        self.bios.load(&mut self.memory)
+    }
+
+    pub fn state(&self) -> RunState {
+        self.state
+    }
+    pub fn is_running(&self) -> bool {
+        self.state == RunState::Running
+    }
+    pub fn halt(&mut self) {
+        self.state = RunState::Halted;
+    }
+    pub(crate) fn exit(&mut self) {
+        self.state = RunState::Exited;
+    }
+    /// Continues execution after a halt, exit or fault, from the current program counter.
+    pub fn resume(&mut self) {
+        self.state = RunState::Running;
     }
 
-    pub fn cycle(&mut self) {
-        println!("Entering processor cycle");
-        Processor::cycle(self).unwrap_or_else(|err| {
-            println!("\n===> ERROR: {:?}", err);
-            Processor::halt(self);
-            panic!();
+    /// Executes a single instruction.
+    ///
+    /// Does nothing while halted, and keeps returning the fault while faulted.
+    pub fn cycle(&mut self) -> Result<(), ProcessorError> {
+        match self.state {
+            RunState::Running => { },
+            RunState::Halted | RunState::Exited => { return Ok(()); },
+            RunState::Faulted(err) => { return Err(err); }
+        }
+
+        // Nothing to execute until the host delivers a key to the waiting `LD Vx, K` instruction,
+        // but emulated time keeps passing.
+        if !self.keypad.is_blocking() {
+            if let Some(mut tracer) = self.tracer.take() {
+                tracer.trace(self);
+                self.tracer = Some(tracer);
+            }
+            if let Err(err) = Processor::cycle(self) {
+                error!(target: "emul8::cpu", "{}", err);
+                debug!(target: "emul8::cpu", "faulted with:\n{}", Processor::halt_dump(self));
+                self.state = RunState::Faulted(err);
+                return Err(err);
+            }
+        }
+
+        if self.timers.step() {
+            self.processor.registers_mut().tick_timers();
+            self.display.vblank();
+            if let Some(mut camera) = self.camera.take() {
+                camera.capture(self);
+                self.camera = Some(camera);
+            }
+            if let Some(mut recorder) = self.recorder.take() {
+                recorder.capture(self);
+                self.recorder = Some(recorder);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the processor until the next 60 Hz boundary, i.e. for one frame of emulated time.
+    pub fn run_frame(&mut self) -> Result<(), ProcessorError> {
+        let frame = self.timers.frames();
+        while self.is_running() && self.timers.frames() == frame {
+            self.cycle()?;
+        }
+        Ok(())
+    }
+
+    /// Runs the machine without a front-end, pressing and releasing keys as scripted, until a
+    /// condition of the script holds or the machine stops.
+    pub fn run_script(&mut self, script: &Script) -> Report {
+        headless::run(self, script)
+    }
+
+    /// Whether the buzzer is currently sounding (the sound timer is non-zero).
+    pub fn is_buzzer_active(&self) -> bool {
+        self.processor.registers().sound_timer() > 0
+    }
+
+    /// Captures the whole state of the machine, in the format described in `snapshot`.
+    ///
+    /// The ROM needs not be kept along with it, as all of memory is captured.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter::new();
+        writer.section(MACHINE_SECTION, |out| {
+            self.variant.write_state(out);
+            self.quirks.write_state(out);
+            out.u8(match self.state {
+                RunState::Running => 0,
+                RunState::Halted | RunState::Faulted(_) => 1,
+                RunState::Exited => 2
+            });
         });
+        writer.section(PROCESSOR_SECTION, |out| self.processor.registers().write_state(out));
+        writer.section(MEMORY_SECTION, |out| self.memory.write_state(out));
+        writer.section(DISPLAY_SECTION, |out| self.display.write_state(out));
+        writer.section(KEYPAD_SECTION, |out| self.keypad.write_state(out));
+        writer.section(AUDIO_SECTION, |out| self.audio.write_state(out));
+        writer.section(TIMERS_SECTION, |out| self.timers.write_state(out));
+        writer.section(RNG_SECTION, |out| out.u64(self.rng.state()));
+        writer.section(FLAGS_SECTION, |out| out.bytes(self.rpl.flags()));
+        writer.finish()
+    }
+    /// Restores the machine to a state captured by `save_state`.
+    ///
+    /// The machine is left untouched if the state can't be restored. A machine saved after a
+    /// fault is restored halted, so that it can be resumed. The random number generator and RPL
+    /// flag storage of the machine are kept, only their state is restored.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let snapshot = Snapshot::parse(data)?;
+
+        let mut machine = snapshot.section(MACHINE_SECTION)?;
+        let variant = Variant::read_state(&mut machine)?;
+        let quirks = Quirks::read_state(&mut machine)?;
+        let state = match machine.u8()? {
+            0 => RunState::Running,
+            1 => RunState::Halted,
+            2 => RunState::Exited,
+            _ => return Err(machine.invalid())
+        };
+        let registers = Registers::read_state(&mut snapshot.section(PROCESSOR_SECTION)?)?;
+        let mut memory = Memory::read_state(&mut snapshot.section(MEMORY_SECTION)?)?;
+        let display = Display::read_state(&mut snapshot.section(DISPLAY_SECTION)?)?;
+        let keypad = Keypad::read_state(&mut snapshot.section(KEYPAD_SECTION)?)?;
+        let audio = Audio::read_state(&mut snapshot.section(AUDIO_SECTION)?)?;
+        let timers = Timers::read_state(&mut snapshot.section(TIMERS_SECTION)?)?;
+        let rng = snapshot.section(RNG_SECTION)?.u64()?;
+        let flags = snapshot.section(FLAGS_SECTION)?.array()?;
+
+        memory.set_tracing(self.memory.is_tracing());
+        self.variant = variant;
+        self.quirks = quirks;
+        self.state = state;
+        *self.processor.registers_mut() = registers;
+        self.memory = memory;
+        self.display = display;
+        self.keypad = keypad;
+        self.audio = audio;
+        self.timers = timers;
+        self.rng.set_state(rng);
+        self.rpl.restore(flags);
+        debug!(target: "emul8::mem", "restored a {} save state", variant);
+        Ok(())
+    }
+
+    pub fn save_state_file(&self, file: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(file, self.save_state())?;
+        Ok(())
+    }
+    pub fn load_state_file(&mut self, file: &str) -> Result<(), Box<dyn Error>> {
+        self.load_state(&fs::read(file)?)?;
+        Ok(())
+    }
+}
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
     }
 }
\ No newline at end of file
@@ -0,0 +1,450 @@
+use crate::chip8::{Chip8, Opcode, Processor, Registers, Rewind, RunState};
+use crate::chip8::memory::{Access, AccessKind, MemoryError};
+use crate::chip8::rewind::RewindError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of instructions a run is given up after, unless something stops it earlier.
+pub const DEFAULT_STEP_LIMIT: u64 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Sp,
+    DelayTimer,
+    SoundTimer
+}
+impl Register {
+    pub fn read(self, registers: &Registers) -> u16 {
+        match self {
+            Register::V(x) => registers.read_v(x) as u16,
+            Register::I => registers.i(),
+            Register::Sp => registers.sp() as u16,
+            Register::DelayTimer => registers.delay_timer() as u16,
+            Register::SoundTimer => registers.sound_timer() as u16
+        }
+    }
+}
+impl FromStr for Register {
+    type Err = String;
+
+    /// Parses `v0` to `vf`, `i`, `sp`, `dt` or `st`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "i" => Ok(Register::I),
+            "sp" => Ok(Register::Sp),
+            "dt" => Ok(Register::DelayTimer),
+            "st" => Ok(Register::SoundTimer),
+            name => name.strip_prefix('v')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+                .map(Register::V)
+                .ok_or_else(|| format!("unknown register `{}`", s))
+        }
+    }
+}
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "v{:x}", x),
+            Register::I => write!(f, "i"),
+            Register::Sp => write!(f, "sp"),
+            Register::DelayTimer => write!(f, "dt"),
+            Register::SoundTimer => write!(f, "st")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual
+}
+impl Comparison {
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal), ("!=", Comparison::NotEqual), ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual), ("<", Comparison::Less), (">", Comparison::Greater)
+    ];
+
+    fn operator(self) -> &'static str {
+        Comparison::OPERATORS.iter().find(|(_, comparison)| *comparison == self).map(|(operator, _)| *operator).unwrap()
+    }
+
+    /// Whether `left` compares to `right` this way.
+    pub fn holds(self, left: u16, right: u16) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right
+        }
+    }
+
+    /// Splits `<left> <operator> <right>` around its first operator, trimming both sides.
+    pub(crate) fn split(s: &str) -> Result<(&str, Comparison, &str), String> {
+        let (at, operator, comparison) = Comparison::OPERATORS.iter()
+            .filter_map(|(operator, comparison)| s.find(operator).map(|at| (at, *operator, *comparison)))
+            .min_by_key(|(at, _, _)| *at)
+            .ok_or_else(|| format!("expected a comparison in `{}`", s))?;
+        Ok((s[..at].trim(), comparison, s[(at + operator.len())..].trim()))
+    }
+}
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.operator())
+    }
+}
+
+/// A condition on the value of a register, such as `v0 == 5` or `i >= 0x300`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16
+}
+impl Condition {
+    pub fn holds(&self, registers: &Registers) -> bool {
+        self.comparison.holds(self.register.read(registers), self.value)
+    }
+}
+impl FromStr for Condition {
+    type Err = String;
+
+    /// Parses `<register> <operator> <value>`, the value in decimal or `0x` prefixed hexadecimal.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (register, comparison, value) = Comparison::split(s)?;
+        let register = register.parse()?;
+        let value = parse_number(value).ok_or_else(|| format!("invalid value `{}`", value))?;
+        Ok(Self { register, comparison, value })
+    }
+}
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {:#X}", self.register, self.comparison, self.value)
+    }
+}
+
+/// Parses a decimal, or `0x` prefixed hexadecimal number.
+pub fn parse_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok()
+    }
+}
+
+/// The accesses a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite
+}
+impl WatchKind {
+    pub fn matches(self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write => kind == AccessKind::Write,
+            WatchKind::ReadWrite => true
+        }
+    }
+}
+
+/// Why the debugger gave control back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The step, or run to a target, completed.
+    Done,
+    /// The program counter reached a breakpoint, with its condition holding if it has one.
+    Breakpoint { addr: u16 },
+    /// The instruction at *pc* accessed the watched address *addr*.
+    Watchpoint { addr: u16, pc: u16, access: Access },
+    /// A `LD Vx, K` instruction is waiting for a key to be pressed and released.
+    WaitingForKey,
+    /// The machine is no longer running.
+    Stopped(RunState),
+    /// The step limit was reached without anything else stopping the run.
+    StepLimit
+}
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Done => write!(f, "done"),
+            StopReason::Breakpoint { addr } => write!(f, "breakpoint at {:#05X}", addr),
+            StopReason::Watchpoint { addr, pc, access } => {
+                let kind = match access.kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "written"
+                };
+                write!(f, "watchpoint: {:#05X} {} by the instruction at {:#05X}", addr, kind, pc)
+            },
+            StopReason::WaitingForKey => write!(f, "waiting for a key"),
+            StopReason::Stopped(RunState::Faulted(err)) => write!(f, "faulted: {}", err),
+            StopReason::Stopped(state) => write!(f, "stopped: {:?}", state),
+            StopReason::StepLimit => write!(f, "step limit reached")
+        }
+    }
+}
+
+/// Runs a machine under control of breakpoints and watchpoints, an instruction at a time, and
+/// steps it back through a `Rewind` history.
+///
+/// Memory tracing is turned on for the machine, so that watchpoints see its accesses.
+pub struct Debugger {
+    system: Chip8,
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    watchpoints: BTreeMap<u16, WatchKind>,
+    step_limit: u64,
+    rewind: Rewind
+}
+impl Debugger {
+    pub fn new(mut system: Chip8) -> Self {
+        system.memory.set_tracing(true);
+        Self {
+            system,
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            step_limit: DEFAULT_STEP_LIMIT,
+            rewind: Rewind::default()
+        }
+    }
+    /// Gives back the machine, with memory tracing turned off.
+    pub fn into_inner(mut self) -> Chip8 {
+        self.system.memory.set_tracing(false);
+        self.system
+    }
+
+    pub fn system(&self) -> &Chip8 {
+        &self.system
+    }
+    pub fn system_mut(&mut self) -> &mut Chip8 {
+        &mut self.system
+    }
+
+    pub fn step_limit(&self) -> u64 {
+        self.step_limit
+    }
+    pub fn set_step_limit(&mut self, step_limit: u64) {
+        self.step_limit = step_limit;
+    }
+
+    /// Sets a breakpoint at `addr`, replacing any already there, that only stops when `condition`
+    /// holds, if given.
+    pub fn set_breakpoint(&mut self, addr: u16, condition: Option<Condition>) {
+        self.breakpoints.insert(addr, condition);
+    }
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+    pub fn breakpoints(&self) -> &BTreeMap<u16, Option<Condition>> {
+        &self.breakpoints
+    }
+
+    pub fn set_watchpoint(&mut self, addr: u16, kind: WatchKind) {
+        self.watchpoints.insert(addr, kind);
+    }
+    pub fn remove_watchpoint(&mut self, addr: u16) -> bool {
+        self.watchpoints.remove(&addr).is_some()
+    }
+    pub fn watchpoints(&self) -> &BTreeMap<u16, WatchKind> {
+        &self.watchpoints
+    }
+
+    /// Decodes the instruction at the program counter.
+    pub fn operation(&self) -> Result<Opcode, MemoryError> {
+        Processor::peek_operation(&self.system)
+    }
+
+    /// Executes the instruction at the program counter.
+    pub fn step(&mut self) -> StopReason {
+        self.execute().unwrap_or(StopReason::Done)
+    }
+    /// Executes the instruction at the program counter, running a called subroutine until it
+    /// returns.
+    pub fn step_over(&mut self) -> StopReason {
+        match self.operation() {
+            Ok(Opcode::_2nnn { .. }) => {
+                let registers = self.system.processor.registers();
+                let (next, sp) = (registers.pc().wrapping_add(2), registers.sp());
+                self.run_until(|system| {
+                    let registers = system.processor.registers();
+                    registers.pc() == next && registers.sp() == sp
+                })
+            },
+            _ => self.step()
+        }
+    }
+    /// Runs until the current subroutine returns. Outside of a subroutine this is just `run`.
+    pub fn step_out(&mut self) -> StopReason {
+        let sp = self.system.processor.registers().sp();
+        self.run_until(|system| system.processor.registers().sp() < sp)
+    }
+    /// Runs until the program counter reaches `addr`.
+    pub fn run_to(&mut self, addr: u16) -> StopReason {
+        self.run_until(|system| system.processor.registers().pc() == addr)
+    }
+    /// Runs until a breakpoint or watchpoint is hit, or the machine stops.
+    pub fn run(&mut self) -> StopReason {
+        self.run_until(|_| false)
+    }
+
+    /// Steps back by one processor cycle.
+    pub fn step_back(&mut self) -> Result<(), RewindError> {
+        self.rewind.step_back(&mut self.system)
+    }
+    /// Steps back to the start of the frame, or of the frame before if already there.
+    pub fn step_back_frame(&mut self) -> Result<(), RewindError> {
+        self.rewind.step_back_frame(&mut self.system)
+    }
+
+    fn run_until<F: Fn(&Chip8) -> bool>(&mut self, done: F) -> StopReason {
+        for _ in 0..self.step_limit {
+            if let Some(reason) = self.execute() {
+                return reason;
+            }
+            if done(&self.system) {
+                return StopReason::Done;
+            }
+
+            let registers = self.system.processor.registers();
+            if let Some(condition) = self.breakpoints.get(&registers.pc()) {
+                if condition.as_ref().map_or(true, |condition| condition.holds(registers)) {
+                    return StopReason::Breakpoint { addr: registers.pc() };
+                }
+            }
+        }
+        StopReason::StepLimit
+    }
+
+    /// Executes one instruction, returning why execution has to stop there, if it does.
+    fn execute(&mut self) -> Option<StopReason> {
+        if let reason @ Some(_) = self.stopped() {
+            return reason;
+        }
+        if self.system.keypad.is_blocking() {
+            return Some(StopReason::WaitingForKey);
+        }
+
+        self.rewind.record(&self.system);
+        let pc = self.system.processor.registers().pc();
+        let stalls = self.system.quirks.display_wait && matches!(self.operation(), Ok(Opcode::_Dxyn { .. }));
+        self.system.memory.take_accesses();
+        loop {
+            let _ = self.system.cycle();
+            // A draw waiting for the start of the next frame is still the same instruction.
+            if !(stalls && self.system.is_running() && self.system.processor.registers().pc() == pc) {
+                break;
+            }
+        }
+
+        if let reason @ Some(_) = self.stopped() {
+            return reason;
+        }
+        for access in self.system.memory.take_accesses() {
+            for (addr, kind) in &self.watchpoints {
+                if kind.matches(access.kind) && access.contains(*addr) {
+                    return Some(StopReason::Watchpoint { addr: *addr, pc, access });
+                }
+            }
+        }
+        None
+    }
+
+    fn stopped(&self) -> Option<StopReason> {
+        match self.system.state() {
+            RunState::Running => None,
+            state => Some(StopReason::Stopped(state))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{boot, Variant};
+
+    const PROGRAM: &str = "
+        : main
+            v0 := 0
+            loop
+                v0 += 1
+                inc
+                i := 0x300
+                save v1
+                while v0 != 5
+            again
+            exit
+        : inc
+            v1 += 2
+            return
+    ";
+
+    fn debugger() -> Debugger {
+        Debugger::new(boot(Variant::SuperChip, PROGRAM))
+    }
+
+    fn pc(debugger: &Debugger) -> u16 {
+        debugger.system().processor.registers().pc()
+    }
+
+    #[test]
+    fn stops_at_breakpoints_when_their_condition_holds() {
+        let mut debugger = debugger();
+        debugger.set_breakpoint(0x206, Some("v0 == 3".parse().unwrap()));
+
+        assert_eq!(debugger.run(), StopReason::Breakpoint { addr: 0x206 });
+        assert_eq!(debugger.system().processor.registers().read_v(0), 3);
+
+        debugger.remove_breakpoint(0x206);
+        assert_eq!(debugger.run(), StopReason::Stopped(RunState::Exited));
+    }
+
+    #[test]
+    fn stops_after_watched_address_is_written() {
+        let mut debugger = debugger();
+        debugger.set_watchpoint(0x301, WatchKind::Read);
+        debugger.set_watchpoint(0x302, WatchKind::ReadWrite);
+        assert_eq!(debugger.run(), StopReason::Stopped(RunState::Exited));
+
+        let mut debugger = self::debugger();
+        debugger.set_watchpoint(0x301, WatchKind::Write);
+        let access = Access { addr: 0x300, len: 2, kind: AccessKind::Write };
+        assert_eq!(debugger.run(), StopReason::Watchpoint { addr: 0x301, pc: 0x208, access });
+        assert_eq!(pc(&debugger), 0x20A);
+    }
+
+    #[test]
+    fn steps_over_into_and_out_of_calls() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.run_to(0x204), StopReason::Done);
+
+        assert_eq!(debugger.step_over(), StopReason::Done);
+        assert_eq!(pc(&debugger), 0x206);
+        assert_eq!(debugger.system().processor.registers().read_v(1), 2);
+
+        assert_eq!(debugger.run_to(0x204), StopReason::Done);
+        assert_eq!(debugger.step(), StopReason::Done);
+        assert_eq!(pc(&debugger), 0x212);
+        assert_eq!(debugger.step_out(), StopReason::Done);
+        assert_eq!(pc(&debugger), 0x206);
+        assert_eq!(debugger.system().processor.registers().read_v(1), 4);
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let condition: Condition = "VF>=0x10".parse().unwrap();
+        assert_eq!(condition, Condition { register: Register::V(0xF), comparison: Comparison::GreaterOrEqual, value: 0x10 });
+        assert_eq!(condition.to_string(), "vf >= 0x10");
+        assert_eq!("i < 512".parse::<Condition>().map(|condition| condition.comparison), Ok(Comparison::Less));
+        assert!("v0 = 1".parse::<Condition>().is_err());
+        assert!("vg == 1".parse::<Condition>().is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! Compression of data to zlib streams, for the images exported, without depending on a library.
+//!
+//! The data is compressed in a single block with the fixed Huffman codes of DEFLATE, matching
+//! repeated sequences up to a bounded search depth. That is far from the best compression, but
+//! the pixels of the machine's display, mostly runs of a few colours, compress well enough.
+
+use crate::chip8::checksum::adler32;
+
+/// Distance in bytes back to which repeated sequences are searched for.
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Number of earlier positions with the same hash that are tried to find the longest match.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+/// Lengths of matches each length code starts at, from code 257, and their number of extra bits.
+const LENGTH_BASES: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99,
+    115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+/// Distances each distance code starts at, and their number of extra bits.
+const DISTANCE_BASES: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769,
+    1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12,
+    12, 13, 13];
+
+/// Compresses `data` to a zlib stream.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32 KiB window at the default level, the header a multiple of 31 as required.
+    let mut out = BitWriter::new(vec![0x78, 0x9C]);
+    // A final block, with the fixed codes.
+    out.bits(0b011, 3);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (len, distance) = longest_match(data, pos, &head, &prev);
+        if len >= MIN_MATCH {
+            out.length(len);
+            out.distance(distance);
+            for pos in pos..pos + len {
+                insert(data, pos, &mut head, &mut prev);
+            }
+            pos += len;
+        } else {
+            out.literal(data[pos] as u16);
+            insert(data, pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+    out.literal(256);
+
+    let mut out = out.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let key = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (key.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Chains the position `pos` to the earlier ones with the same hash.
+fn insert(data: &[u8], pos: usize, head: &mut [usize], prev: &mut [usize]) {
+    if pos + MIN_MATCH <= data.len() {
+        let hash = hash(&data[pos..]);
+        prev[pos % WINDOW] = head[hash];
+        head[hash] = pos;
+    }
+}
+
+/// The length and distance of the longest earlier sequence matching the data at `pos`, with the
+/// positions of earlier sequences chained by their hash in `head` and `prev`.
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let max = (data.len() - pos).min(MAX_MATCH);
+    let (mut best, mut distance) = (0, 0);
+    let mut candidate = head[hash(&data[pos..])];
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || pos - candidate > WINDOW {
+            break;
+        }
+        let len = (0..max).take_while(|i| data[candidate + i] == data[pos + i]).count();
+        if len > best {
+            best = len;
+            distance = pos - candidate;
+            if len == max {
+                break;
+            }
+        }
+        // Positions further back in the window have been replaced by newer ones.
+        match prev[candidate % WINDOW] {
+            next if next < candidate => candidate = next,
+            _ => break
+        }
+    }
+    (best, distance)
+}
+
+/// Writes bits starting from the least significant bit of each byte, as DEFLATE and GIF do.
+pub(crate) struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    len: u32
+}
+impl BitWriter {
+    /// Creates a writer appending to `out`.
+    pub(crate) fn new(out: Vec<u8>) -> Self {
+        Self { out, bits: 0, len: 0 }
+    }
+
+    pub(crate) fn bits(&mut self, value: u32, len: u32) {
+        self.bits |= value << self.len;
+        self.len += len;
+        while self.len >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.len -= 8;
+        }
+    }
+    /// Writes a Huffman code, which starts from its most significant bit.
+    fn code(&mut self, code: u32, len: u32) {
+        self.bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    /// Writes the fixed code of a literal byte, or of the end of the block, or of a length.
+    fn literal(&mut self, value: u16) {
+        let value = value as u32;
+        match value {
+            0..=143 => self.code(0x30 + value, 8),
+            144..=255 => self.code(0x190 + value - 144, 9),
+            256..=279 => self.code(value - 256, 7),
+            _ => self.code(0xC0 + value - 280, 8)
+        }
+    }
+    fn length(&mut self, len: usize) {
+        let code = LENGTH_BASES.iter().rposition(|base| *base as usize <= len).unwrap();
+        self.literal(257 + code as u16);
+        self.bits((len - LENGTH_BASES[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+    }
+    fn distance(&mut self, distance: usize) {
+        let code = DISTANCE_BASES.iter().rposition(|base| *base as usize <= distance).unwrap();
+        self.code(code as u32, 5);
+        self.bits((distance - DISTANCE_BASES[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+    }
+
+    /// Pads the last byte with zeros, returning the bytes written.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compresses_to_zlib_streams() {
+        assert_eq!(zlib(b""), [0x78, 0x9C, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(zlib(b"abc"), [0x78, 0x9C, 0x4B, 0x4C, 0x4A, 0x06, 0x00, 0x02, 0x4D, 0x01, 0x27]);
+        // Repeats are matched, even overlapping the bytes being repeated.
+        let zeros = zlib(&[0; 1000]);
+        assert!(zeros.len() < 20, "{:02X?}", zeros);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,276 @@
+use crate::chip8::{Opcode, Variant};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+
+/// Number of data bytes listed per line.
+const DATA_BYTES_PER_LINE: usize = 8;
+
+pub enum Line {
+    Code { addr: u16, bytes: Vec<u8>, operation: Opcode },
+    Data { addr: u16, bytes: Vec<u8> }
+}
+impl Line {
+    pub fn addr(&self) -> u16 {
+        match self {
+            Line::Code { addr, .. } | Line::Data { addr, .. } => *addr
+        }
+    }
+}
+
+/// An address annotated listing of a piece of memory.
+pub struct Disassembly {
+    pub lines: Vec<Line>,
+    /// Labels of the jump and call targets, and of the data pointed to by I, within the listing.
+    pub labels: BTreeMap<u16, String>
+}
+impl Disassembly {
+    fn address(&self, addr: u16) -> String {
+        match self.labels.get(&addr) {
+            Some(label) => label.clone(),
+            None => format!("{:#05X}", addr)
+        }
+    }
+
+    /// Formats the listing, marking the line at `cursor` if given.
+    pub fn listing(&self, cursor: Option<u16>) -> String {
+        let mut writer = String::new();
+
+        for line in &self.lines {
+            if let Some(label) = self.labels.get(&line.addr()) {
+                writeln!(writer, "{}:", label).unwrap();
+            }
+
+            let marker = if cursor == Some(line.addr()) { ">" } else { " " };
+            match line {
+                Line::Code { addr, bytes, operation } => {
+                    let words: Vec<String> = bytes.chunks(2).map(|word| format!("{:02X}{:02X}", word[0], word[1])).collect();
+                    writeln!(writer, "{} {:04X}:  {:<11}{}", marker, addr, words.join(" "),
+                             operation.to_asm(|addr| self.address(addr))).unwrap();
+                },
+                Line::Data { addr, bytes } => {
+                    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+                    writeln!(writer, "{} {:04X}:  {:<11}DB {}", marker, addr, "", bytes.join(", ")).unwrap();
+                }
+            }
+        }
+
+        writer
+    }
+}
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.listing(None))
+    }
+}
+
+pub struct Disassembler {
+    variant: Variant
+}
+impl Disassembler {
+    pub fn new(variant: Variant) -> Self {
+        Self {
+            variant
+        }
+    }
+
+    /// Disassembles `data` loaded at `origin`, telling code from data by following every path of
+    /// execution from the `entry_points`.
+    ///
+    /// Paths end at returns, exits, computed jumps (`JP V0, addr`), and at words that are not
+    /// instructions of the variant; whatever is never reached is listed as data.
+    pub fn disassemble(&self, data: &[u8], origin: u16, entry_points: &[u16]) -> Disassembly {
+        let mut starts = BTreeSet::new();
+        let mut covered = vec![false; data.len()];
+        let mut pending: Vec<u16> = entry_points.to_vec();
+
+        while let Some(mut addr) = pending.pop() {
+            while let Some(operation) = self.decode(data, origin, addr) {
+                let offset = (addr - origin) as usize;
+                let size = operation.size() as usize;
+                if starts.contains(&addr) || covered[offset..(offset + size)].iter().any(|byte| *byte) {
+                    break;
+                }
+                starts.insert(addr);
+                covered[offset..(offset + size)].iter_mut().for_each(|byte| *byte = true);
+
+                let next = addr.wrapping_add(size as u16);
+                match operation {
+                    Opcode::_1nnn { n } => {
+                        pending.push(n);
+                        break;
+                    },
+                    Opcode::_2nnn { n } => pending.push(n),
+                    Opcode::_3xkk { .. } | Opcode::_4xkk { .. } | Opcode::_5xy0 { .. } | Opcode::_9xy0 { .. } |
+                    Opcode::_Ex9E { .. } | Opcode::_ExA1 { .. } => {
+                        let skipped = self.decode(data, origin, next).map_or(2, |operation| operation.size());
+                        pending.push(next.wrapping_add(skipped));
+                    },
+                    Opcode::_00EE | Opcode::_00FD | Opcode::_0nnn { .. } | Opcode::_Bnnn { .. } => break,
+                    _ => { }
+                }
+                addr = next;
+            }
+        }
+
+        self.build(data, origin, &starts)
+    }
+
+    /// Disassembles `data` loaded at `origin`, taking every aligned word that decodes to an
+    /// instruction as code.
+    pub fn disassemble_linear(&self, data: &[u8], origin: u16) -> Disassembly {
+        let mut starts = BTreeSet::new();
+
+        let mut offset = 0;
+        while offset < data.len() {
+            let addr = origin.wrapping_add(offset as u16);
+            match self.decode(data, origin, addr) {
+                Some(operation) => {
+                    starts.insert(addr);
+                    offset += operation.size() as usize;
+                },
+                None => offset += 2
+            }
+        }
+
+        self.build(data, origin, &starts)
+    }
+
+    /// Decodes the instruction at `addr`, unless it is out of range or not an instruction.
+    fn decode(&self, data: &[u8], origin: u16, addr: u16) -> Option<Opcode> {
+        let offset = addr.checked_sub(origin)? as usize;
+        let word = |offset: usize| data.get(offset..(offset + 2)).map(|word| u16::from_be_bytes([word[0], word[1]]));
+
+        let operation = Opcode::from(word(offset)?);
+        let operation = match operation.size() {
+            2 => operation,
+            _ => Opcode::from_long(word(offset)?, word(offset + 2)?)
+        };
+
+        match operation {
+            Opcode::Invalid { .. } => None,
+            _ if !self.variant.supports(&operation) => None,
+            _ => Some(operation)
+        }
+    }
+
+    fn build(&self, data: &[u8], origin: u16, starts: &BTreeSet<u16>) -> Disassembly {
+        let end = origin as usize + data.len();
+        let in_range = |addr: u16| addr >= origin && (addr as usize) < end;
+
+        let code: BTreeMap<u16, Opcode> = starts.iter()
+            .filter_map(|addr| self.decode(data, origin, *addr).map(|operation| (*addr, operation)))
+            .collect();
+
+        let mut labels = BTreeMap::new();
+        for operation in code.values() {
+            match *operation {
+                Opcode::_2nnn { n } if in_range(n) => {
+                    labels.insert(n, format!("sub_{:03X}", n));
+                },
+                Opcode::_1nnn { n } | Opcode::_Bnnn { n } if in_range(n) => {
+                    labels.entry(n).or_insert_with(|| format!("label_{:03X}", n));
+                },
+                _ => { }
+            }
+        }
+        for operation in code.values() {
+            if let Opcode::_Annn { n } | Opcode::_F000 { n } = *operation {
+                if in_range(n) {
+                    labels.entry(n).or_insert_with(|| format!("data_{:03X}", n));
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let addr = origin.wrapping_add(offset as u16);
+            match code.get(&addr) {
+                Some(operation) => {
+                    let size = operation.size() as usize;
+                    lines.push(Line::Code { addr, bytes: data[offset..(offset + size)].to_vec(), operation: *operation });
+                    offset += size;
+                },
+                None => {
+                    // Data runs until the next instruction or label.
+                    let mut len = 1;
+                    while offset + len < data.len() && len < DATA_BYTES_PER_LINE {
+                        let next = addr.wrapping_add(len as u16);
+                        if code.contains_key(&next) || labels.contains_key(&next) {
+                            break;
+                        }
+                        len += 1;
+                    }
+                    lines.push(Line::Data { addr, bytes: data[offset..(offset + len)].to_vec() });
+                    offset += len;
+                }
+            }
+        }
+
+        Disassembly { lines, labels }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_every_instruction_of_the_variant_as_code() {
+        // The last word is the SUPER-CHIP `HIGH`.
+        let data = [0x00, 0xE0, 0xA2, 0x06, 0xFF, 0xFF, 0x12, 0x00, 0x00, 0xFF];
+        assert_eq!(Disassembler::new(Variant::Chip8).disassemble_linear(&data, 0x200).to_string(), "\
+label_200:
+  0200:  00E0       CLS
+  0202:  A206       LD I, data_206
+  0204:             DB 0xFF, 0xFF
+data_206:
+  0206:  1200       JP label_200
+  0208:             DB 0x00, 0xFF
+");
+        let listing = Disassembler::new(Variant::SuperChip).disassemble_linear(&data, 0x200).listing(Some(0x208));
+        assert!(listing.ends_with("> 0208:  00FF       HIGH\n"));
+    }
+
+    #[test]
+    fn follows_the_flow_of_execution() {
+        // Calls a subroutine pointing I at data, then loops; what is never reached is data, even if it
+        // would decode as an instruction.
+        let program = [0x22, 0x06, 0x12, 0x02, 0x12, 0x34, 0xA2, 0x0C, 0x00, 0xEE, 0x00, 0xE0, 0xF0, 0x90];
+        assert_eq!(Disassembler::new(Variant::Chip8).disassemble(&program, 0x200, &[0x200]).to_string(),
+            "  0200:  2206       CALL sub_206
+label_202:
+  0202:  1202       JP label_202
+  0204:             DB 0x12, 0x34
+sub_206:
+  0206:  A20C       LD I, data_20C
+  0208:  00EE       RET
+  020A:             DB 0x00, 0xE0
+data_20C:
+  020C:             DB 0xF0, 0x90
+");
+    }
+
+    #[test]
+    fn follows_both_ways_of_a_skip() {
+        // Skips either a 4 byte `LD I, long` or a jump, past which execution goes on.
+        let program = [0x30, 0x00, 0xF0, 0x00, 0x02, 0x0A, 0x40, 0x00, 0x12, 0x10, 0x00, 0xFD, 0xFF, 0xFF];
+        let disassembly = Disassembler::new(Variant::XoChip).disassemble(&program, 0x200, &[0x200]);
+        let code: Vec<u16> = disassembly.lines.iter()
+            .filter_map(|line| match line { Line::Code { addr, .. } => Some(*addr), _ => None })
+            .collect();
+        assert_eq!(code, vec![0x200, 0x202, 0x206, 0x208, 0x20A]);
+        assert_eq!(disassembly.labels.get(&0x20A).map(String::as_str), Some("data_20A"));
+    }
+
+    #[test]
+    fn splits_data_into_lines_of_8_bytes() {
+        let mut data = vec![0x12, 0x00];
+        data.extend_from_slice(&[0xFF; 10]);
+        let disassembly = Disassembler::new(Variant::Chip8).disassemble(&data, 0x200, &[0x200]);
+        let lines: Vec<(u16, usize)> = disassembly.lines.iter()
+            .map(|line| match line { Line::Code { addr, bytes, .. } | Line::Data { addr, bytes } => (*addr, bytes.len()) })
+            .collect();
+        assert_eq!(lines, vec![(0x200, 2), (0x202, 8), (0x20A, 2)]);
+    }
+}
\ No newline at end of file
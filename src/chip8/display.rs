@@ -0,0 +1,280 @@
+use crate::chip8::snapshot::{SectionReader, SectionWriter, SnapshotError};
+
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+/// Width of the SUPER-CHIP high resolution mode.
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+/// Height of the SUPER-CHIP high resolution mode.
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+/// Number of XO-CHIP bitplanes.
+pub const PLANE_COUNT: usize = 2;
+
+pub struct Display {
+    /// The framebuffer, stored row by row.
+    ///
+    /// Each pixel holds one bit per bitplane, bit 0 being the first plane. Together, the bits
+    /// make up the colour of the pixel (0 - 3); without XO-CHIP only the first plane is ever drawn
+    /// to, so a pixel is either off (0) or on (1). Pixel (*x*, *y*) lives at index
+    /// *y* * `width` + *x*.
+    frame: Vec<u8>,
+    width: usize,
+    height: usize,
+    /// Bitmask of the planes selected for drawing, clearing and scrolling.
+    planes: u8,
+    /// Set at every 60 Hz vertical blank, cleared by a draw waiting for it.
+    vblank: bool
+}
+impl Display {
+    pub fn new() -> Self {
+        Self {
+            frame: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            width: DISPLAY_WIDTH,
+            height: DISPLAY_HEIGHT,
+            planes: 0x1,
+            vblank: false
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == HIRES_DISPLAY_WIDTH
+    }
+    /// Switches between the 64x32 and the 128x64 resolution, clearing the screen.
+    pub fn set_hires(&mut self, hires: bool) {
+        let (width, height) = if hires {
+            (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)
+        } else {
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        };
+
+        self.width = width;
+        self.height = height;
+        self.frame = vec![0; width * height];
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+    /// Selects the bitplanes to operate on; only the low `PLANE_COUNT` bits of `planes` are used.
+    pub fn set_planes(&mut self, planes: u8) {
+        self.planes = planes & ((1 << PLANE_COUNT) - 1) as u8;
+    }
+    pub fn plane_count(&self) -> usize {
+        self.planes.count_ones() as usize
+    }
+
+    /// The colour of every pixel, row by row.
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        self.frame[y * self.width + x]
+    }
+    /// Whether the pixel is lit on any plane.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.color(x, y) != 0
+    }
+
+    /// Clears the selected planes.
+    pub fn clear(&mut self) {
+        let mask = !self.planes;
+        self.frame.iter_mut().for_each(|pixel| *pixel &= mask);
+    }
+
+    pub fn vblank(&mut self) {
+        self.vblank = true;
+    }
+    /// Consumes the vertical blank signal, returning whether one occurred since the last call.
+    pub fn take_vblank(&mut self) -> bool {
+        std::mem::replace(&mut self.vblank, false)
+    }
+
+    /// XORs an 8 pixel wide sprite onto the selected planes, one byte per row.
+    ///
+    /// `sprite` holds a sprite of equal height for each selected plane, one after the other.
+    ///
+    /// The starting coordinates always wrap around the screen. The rows and columns of the sprite
+    /// that fall past the right or bottom edge are either clipped, or wrapped around as well.
+    ///
+    /// Returns whether any pixel was switched from on to off (a *collision*).
+    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite: &[u8], clip: bool) -> bool {
+        self.draw(x, y, sprite, 1, clip)
+    }
+    /// XORs a 16x16 SUPER-CHIP sprite onto the selected planes, two bytes per row.
+    ///
+    /// See [`draw_sprite`](Display::draw_sprite) for how the planes and the edges of the screen are
+    /// handled.
+    pub fn draw_large_sprite(&mut self, x: u8, y: u8, sprite: &[u8], clip: bool) -> bool {
+        self.draw(x, y, sprite, 2, clip)
+    }
+    fn draw(&mut self, x: u8, y: u8, sprite: &[u8], row_width: usize, clip: bool) -> bool {
+        let (x, y) = (x as usize % self.width, y as usize % self.height);
+        let mut collision = false;
+
+        let plane_len = sprite.len() / self.plane_count().max(1);
+        let selected = self.planes;
+        let planes = (0..PLANE_COUNT).map(|plane| 1u8 << plane).filter(|plane| selected & plane != 0);
+
+        for (plane, data) in planes.zip(sprite.chunks(plane_len.max(1))) {
+            for (row, bytes) in data.chunks(row_width).enumerate() {
+                let py = y + row;
+                if py >= self.height && clip {
+                    break;
+                }
+                let py = py % self.height;
+
+                for col in 0..(bytes.len() * 8) {
+                    let px = x + col;
+                    if px >= self.width && clip {
+                        break;
+                    }
+                    let px = px % self.width;
+
+                    if (bytes[col / 8] >> (7 - col % 8)) & 0x1 == 0x1 {
+                        let pixel = &mut self.frame[py * self.width + px];
+                        collision |= *pixel & plane != 0;
+                        *pixel ^= plane;
+                    }
+                }
+            }
+        }
+
+        collision
+    }
+
+    /// Scrolls the selected planes down by `n` pixels; rows scrolled in at the top are blank.
+    pub fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.height);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let from = if y >= n { self.frame[(y - n) * self.width + x] } else { 0 };
+                self.scroll_pixel(x, y, from);
+            }
+        }
+    }
+    /// Scrolls the selected planes left by `n` pixels; columns scrolled in on the right are blank.
+    pub fn scroll_left(&mut self, n: usize) {
+        let n = n.min(self.width);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let from = if x + n < self.width { self.frame[y * self.width + x + n] } else { 0 };
+                self.scroll_pixel(x, y, from);
+            }
+        }
+    }
+    /// Scrolls the selected planes right by `n` pixels; columns scrolled in on the left are blank.
+    pub fn scroll_right(&mut self, n: usize) {
+        let n = n.min(self.width);
+        for y in 0..self.height {
+            for x in (0..self.width).rev() {
+                let from = if x >= n { self.frame[y * self.width + x - n] } else { 0 };
+                self.scroll_pixel(x, y, from);
+            }
+        }
+    }
+    /// Replaces the selected planes of a pixel with those of `from`.
+    fn scroll_pixel(&mut self, x: usize, y: usize, from: u8) {
+        let pixel = &mut self.frame[y * self.width + x];
+        *pixel = (*pixel & !self.planes) | (from & self.planes);
+    }
+
+    pub fn dump(&self) -> String {
+        let mut writer = String::new();
+
+        for row in self.frame.chunks(self.width) {
+            for pixel in row {
+                writer.push(match pixel {
+                    0 => '.',
+                    1 => '#',
+                    2 => '+',
+                    _ => '@'
+                });
+            }
+            writer.push('\n');
+        }
+
+        writer
+    }
+
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.bool(self.is_hires());
+        out.u8(self.planes);
+        out.bool(self.vblank);
+        out.bytes(&self.frame);
+    }
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, SnapshotError> {
+        let mut display = Self::new();
+        display.set_hires(input.bool()?);
+        display.planes = input.u8()?;
+        display.vblank = input.bool()?;
+        let frame = input.bytes()?;
+
+        let colors = 1 << PLANE_COUNT;
+        if display.planes >= colors || frame.len() != display.frame.len() || frame.iter().any(|color| *color >= colors) {
+            return Err(input.invalid());
+        }
+        display.frame.copy_from_slice(frame);
+        Ok(display)
+    }
+}
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_collisions() {
+        let mut display = Display::new();
+        assert!(!display.draw_sprite(0, 0, &[0b1100_0000], false));
+        assert!(display.pixel(0, 0) && display.pixel(1, 0));
+
+        // Drawing over a lit pixel turns it off, and is a collision.
+        assert!(display.draw_sprite(1, 0, &[0b1100_0000], false));
+        assert!(display.pixel(0, 0) && !display.pixel(1, 0) && display.pixel(2, 0));
+        assert!(!display.draw_sprite(4, 4, &[0xFF], false));
+    }
+
+    #[test]
+    fn wraps_or_clips_at_the_edges() {
+        let mut wrapped = Display::new();
+        wrapped.draw_sprite(62, 31, &[0xF0, 0x80], false);
+        assert!(wrapped.pixel(62, 31) && wrapped.pixel(63, 31) && wrapped.pixel(0, 31) && wrapped.pixel(1, 31));
+        assert!(wrapped.pixel(62, 0));
+
+        let mut clipped = Display::new();
+        clipped.draw_sprite(62, 31, &[0xF0, 0x80], true);
+        assert!(clipped.pixel(62, 31) && clipped.pixel(63, 31));
+        assert_eq!(clipped.frame().iter().filter(|pixel| **pixel != 0).count(), 2);
+
+        // The starting coordinates wrap around either way.
+        clipped.clear();
+        clipped.draw_sprite(64 + 3, 32 + 2, &[0x80], true);
+        assert!(clipped.pixel(3, 2));
+    }
+
+    #[test]
+    fn clears_the_selected_planes() {
+        let mut display = Display::new();
+        display.set_planes(0x3);
+        display.draw_sprite(0, 0, &[0x80, 0x80], false);
+        assert_eq!(display.color(0, 0), 3);
+
+        display.set_planes(0x1);
+        display.clear();
+        assert_eq!(display.color(0, 0), 2);
+        display.set_planes(0x3);
+        display.clear();
+        assert!(display.frame().iter().all(|pixel| *pixel == 0));
+    }
+}
\ No newline at end of file
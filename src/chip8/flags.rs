@@ -0,0 +1,90 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Number of RPL user flags available to `LD R, Vx` and `LD Vx, R`.
+pub const FLAG_COUNT: usize = 16;
+
+/// Persists the RPL user flags between runs, the way the HP-48 kept them in its RPL registers.
+pub trait FlagStorage {
+    /// Returns the previously saved flags, if there are any.
+    fn load(&mut self) -> io::Result<Option<[u8; FLAG_COUNT]>>;
+    fn save(&mut self, flags: &[u8; FLAG_COUNT]) -> io::Result<()>;
+}
+
+/// Keeps the flags in a file, as raw bytes.
+pub struct FileFlagStorage {
+    path: PathBuf
+}
+impl FileFlagStorage {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into()
+        }
+    }
+}
+impl FlagStorage for FileFlagStorage {
+    fn load(&mut self) -> io::Result<Option<[u8; FLAG_COUNT]>> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                let mut flags = [0; FLAG_COUNT];
+                let len = data.len().min(FLAG_COUNT);
+                flags[..len].copy_from_slice(&data[..len]);
+                Ok(Some(flags))
+            },
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err)
+        }
+    }
+    fn save(&mut self, flags: &[u8; FLAG_COUNT]) -> io::Result<()> {
+        fs::write(&self.path, flags)
+    }
+}
+
+/// The SUPER-CHIP RPL user flags.
+pub struct RplFlags {
+    flags: [u8; FLAG_COUNT],
+    storage: Option<Box<dyn FlagStorage>>
+}
+impl RplFlags {
+    pub fn new() -> Self {
+        Self {
+            flags: [0; FLAG_COUNT],
+            storage: None
+        }
+    }
+
+    /// Attaches a persistence hook, restoring the flags it has saved before.
+    pub fn set_storage(&mut self, mut storage: Box<dyn FlagStorage>) -> io::Result<()> {
+        if let Some(flags) = storage.load()? {
+            self.flags = flags;
+        }
+        self.storage = Some(storage);
+        Ok(())
+    }
+
+    pub fn flags(&self) -> &[u8; FLAG_COUNT] {
+        &self.flags
+    }
+
+    /// Stores `values` into the first flags, saving them to the storage if there is one.
+    ///
+    /// The flags are updated even if saving fails.
+    pub fn store(&mut self, values: &[u8]) -> io::Result<()> {
+        self.flags[..values.len()].copy_from_slice(values);
+        match &mut self.storage {
+            Some(storage) => storage.save(&self.flags),
+            None => Ok(())
+        }
+    }
+
+    /// Replaces all of the flags, without saving them to the storage.
+    pub(crate) fn restore(&mut self, flags: [u8; FLAG_COUNT]) {
+        self.flags = flags;
+    }
+}
+impl Default for RplFlags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
\ No newline at end of file
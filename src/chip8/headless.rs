@@ -0,0 +1,229 @@
+//! Scripted runs without a front-end, bounded in time or stopped on a condition, for automated
+//! tests.
+
+use crate::chip8::{Chip8, RunState};
+use crate::chip8::debugger::{parse_number, Comparison};
+use crate::chip8::processor::ProcessorError;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// A condition on a byte of memory, such as `[0x300] == 5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryCondition {
+    pub addr: u16,
+    pub comparison: Comparison,
+    pub value: u8
+}
+impl MemoryCondition {
+    /// Whether the condition holds; never for an address outside of memory.
+    pub fn holds(&self, system: &Chip8) -> bool {
+        match system.memory.read(self.addr) {
+            Ok(byte) => self.comparison.holds(byte as u16, self.value as u16),
+            Err(_) => false
+        }
+    }
+}
+impl FromStr for MemoryCondition {
+    type Err = String;
+
+    /// Parses `[<addr>] <operator> <value>`, the numbers in decimal or `0x` prefixed hexadecimal.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, comparison, value) = Comparison::split(s)?;
+        let addr = addr.strip_prefix('[').and_then(|addr| addr.strip_suffix(']')).map(str::trim)
+            .ok_or_else(|| format!("expected an address in brackets, found `{}`", addr))?;
+        let addr = parse_number(addr).ok_or_else(|| format!("invalid address `{}`", addr))?;
+        let value = parse_number(value).and_then(|value| u8::try_from(value).ok())
+            .ok_or_else(|| format!("invalid byte `{}`", value))?;
+        Ok(Self { addr, comparison, value })
+    }
+}
+impl fmt::Display for MemoryCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:#05X}] {} {:#04X}", self.addr, self.comparison, self.value)
+    }
+}
+
+/// A condition stopping a headless run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Until {
+    /// The run lasted this many frames.
+    Frames(u64),
+    /// The run lasted this many cycles.
+    Cycles(u64),
+    /// The program counter reached the address; the instruction there is not executed.
+    Pc(u16),
+    Memory(MemoryCondition)
+}
+impl Until {
+    /// Whether the condition holds for `system`, `frames` and `cycles` into the run.
+    fn holds(&self, system: &Chip8, frames: u64, cycles: u64) -> bool {
+        match self {
+            Until::Frames(limit) => frames >= *limit,
+            Until::Cycles(limit) => cycles >= *limit,
+            Until::Pc(addr) => system.processor.registers().pc() == *addr,
+            Until::Memory(condition) => condition.holds(system)
+        }
+    }
+}
+impl fmt::Display for Until {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Until::Frames(frames) => write!(f, "{} frames", frames),
+            Until::Cycles(cycles) => write!(f, "{} cycles", cycles),
+            Until::Pc(addr) => write!(f, "pc == {:#05X}", addr),
+            Until::Memory(condition) => write!(f, "{}", condition)
+        }
+    }
+}
+
+/// A key pressed or released at the start of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The frame into the run.
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool
+}
+
+/// What a headless run does: the keys pressed and released along the way, and when it stops.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    /// The run stops as soon as any of these holds, or the machine stops by itself. Without any,
+    /// a program that never exits runs forever.
+    pub until: Vec<Until>,
+    pub keys: Vec<KeyEvent>
+}
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Why a headless run stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Until(Until),
+    /// The machine was halted, or exited.
+    Stopped(RunState),
+    Faulted(ProcessorError)
+}
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Until(until) => write!(f, "reached {}", until),
+            Stop::Stopped(state) => write!(f, "machine {:?}", state),
+            Stop::Faulted(err) => write!(f, "faulted: {}", err)
+        }
+    }
+}
+
+/// How a headless run went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub stop: Stop,
+    /// The frames and cycles the run lasted.
+    pub frames: u64,
+    pub cycles: u64
+}
+
+/// Runs `system` as scripted; see `Chip8::run_script`.
+pub(crate) fn run(system: &mut Chip8, script: &Script) -> Report {
+    let (start_frame, start_cycles) = (system.timers.frames(), system.timers.cycles());
+    let mut keys = script.keys.clone();
+    keys.sort_by_key(|event| event.frame);
+    let mut keys = keys.iter().peekable();
+
+    loop {
+        let (frames, cycles) = (system.timers.frames() - start_frame, system.timers.cycles() - start_cycles);
+        while let Some(event) = keys.next_if(|event| event.frame <= frames) {
+            if event.pressed { system.keypad.press(event.key) } else { system.keypad.release(event.key) }
+        }
+
+        let stop = match system.state() {
+            _ if system.is_running() => script.until.iter().find(|until| until.holds(system, frames, cycles))
+                .map(|until| Stop::Until(*until)),
+            RunState::Faulted(err) => Some(Stop::Faulted(err)),
+            state => Some(Stop::Stopped(state))
+        };
+        if let Some(stop) = stop {
+            return Report { stop, frames, cycles };
+        }
+
+        // A fault is reported by the state of the machine.
+        let _ = system.cycle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{boot, Variant};
+
+    /// Counts in v1 how often key 5 is seen pressed, storing the count at 0x301, and exits once it
+    /// reaches 10.
+    fn system() -> Chip8 {
+        boot(Variant::SuperChip, "
+            : main
+                v0 := 5
+                i := 0x300
+                loop
+                    if v0 key then v1 += 1
+                    save v1
+                    if v1 == 10 then exit
+                    i := 0x300
+                again
+        ")
+    }
+
+    #[test]
+    fn stops_on_the_first_condition_to_hold() {
+        let mut system = system();
+        let script = Script { until: vec![Until::Frames(3), Until::Pc(0x204)], keys: Vec::new() };
+        assert_eq!(system.run_script(&script), Report { stop: Stop::Until(Until::Pc(0x204)), frames: 0, cycles: 2 });
+
+        let script = Script { until: vec![Until::Frames(3), Until::Cycles(1000)], keys: Vec::new() };
+        assert_eq!(system.run_script(&script), Report { stop: Stop::Until(Until::Frames(3)), frames: 3, cycles: 28 });
+    }
+
+    #[test]
+    fn feeds_the_scripted_keys() {
+        let mut system = system();
+        let condition: MemoryCondition = "[0x301] >= 3".parse().unwrap();
+        let script = Script {
+            until: vec![Until::Memory(condition), Until::Frames(10)],
+            keys: vec![KeyEvent { frame: 4, key: 5, pressed: false }, KeyEvent { frame: 1, key: 5, pressed: true }]
+        };
+
+        let report = system.run_script(&script);
+        assert_eq!((report.stop, report.frames), (Stop::Until(Until::Memory(condition)), 2));
+        assert_eq!(system.memory.read(0x301), Ok(3));
+
+        // The frames of the keys are counted from the start of each run.
+        let script = Script { until: vec![Until::Frames(10)], keys: vec![KeyEvent { frame: 2, key: 5, pressed: false }] };
+        assert_eq!(system.run_script(&script).stop, Stop::Until(Until::Frames(10)));
+        assert_eq!(system.memory.read(0x301), Ok(5));
+    }
+
+    #[test]
+    fn stops_with_the_machine() {
+        let mut exiting = system();
+        exiting.keypad.press(5);
+        assert_eq!(exiting.run_script(&Script::new()).stop, Stop::Stopped(RunState::Exited));
+
+        let mut faulting = system();
+        faulting.memory.write(0x200, 0x00).unwrap();
+        let report = faulting.run_script(&Script::new());
+        assert!(matches!(report.stop, Stop::Faulted(ProcessorError::UnsupportedOpcodeError { .. })));
+        assert_eq!(report.stop.to_string(), "faulted: unsupported opcode 0x0005 at 0x200");
+    }
+
+    #[test]
+    fn parses_memory_conditions() {
+        let condition: MemoryCondition = "[0x300] != 0x1F".parse().unwrap();
+        assert_eq!(condition, MemoryCondition { addr: 0x300, comparison: Comparison::NotEqual, value: 0x1F });
+        assert_eq!(condition.to_string(), "[0x300] != 0x1F");
+        assert!("0x300 == 1".parse::<MemoryCondition>().is_err());
+        assert!("[0x300] == 256".parse::<MemoryCondition>().is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::chip8::snapshot::{SectionReader, SectionWriter, SnapshotError};
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Keypad {
+    /// Pressed state of the hexadecimal keys (0x0 - 0xF).
+    keys: [bool; 16],
+
+    /// Whether an `LD Vx, K` instruction is waiting for a key.
+    waiting: bool,
+    /// The key pressed and released while waiting, not yet consumed by the processor.
+    released: Option<u8>,
+    /// Keys held since before the wait started, as a mask; releasing them doesn't count.
+    held: u16
+}
+impl Keypad {
+    pub fn new() -> Self {
+        Self {
+            keys: [false; 16],
+            waiting: false,
+            released: None,
+            held: 0
+        }
+    }
+
+    /// Presses `key`; keys past 0xF, which the keypad doesn't have, are ignored.
+    pub fn press(&mut self, key: u8) {
+        if let Some(pressed) = self.keys.get_mut(key as usize) {
+            *pressed = true;
+        }
+    }
+    /// Releases `key`; keys past 0xF are ignored.
+    pub fn release(&mut self, key: u8) {
+        if let Some(pressed) = self.keys.get_mut(key as usize) {
+            if self.waiting && *pressed && self.held & (1 << key) == 0 {
+                self.released = Some(key);
+            }
+            *pressed = false;
+            self.held &= !(1 << key);
+        }
+    }
+    /// Whether `key` is pressed; never for keys past 0xF.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys.get(key as usize).copied().unwrap_or(false)
+    }
+
+    /// Whether the processor is stalled on an `LD Vx, K` instruction with no key to consume yet.
+    ///
+    /// The host can use this to block on its own input events instead of cycling the processor.
+    pub fn is_blocking(&self) -> bool {
+        self.waiting && self.released.is_none()
+    }
+
+    /// Polls for a key on behalf of `LD Vx, K`.
+    ///
+    /// The first call starts waiting; only a key that is pressed *and* released after that point
+    /// is reported, after which the wait is over. Keys already held then have to be released and
+    /// pressed again, as on the COSMAC VIP.
+    pub fn wait_for_key(&mut self) -> Option<u8> {
+        if !self.waiting {
+            self.waiting = true;
+            self.released = None;
+            self.held = self.mask();
+        }
+
+        let key = self.released.take();
+        if key.is_some() {
+            self.waiting = false;
+        }
+        key
+    }
+
+    /// The keys pressed, as a mask.
+    fn mask(&self) -> u16 {
+        self.keys.iter().rev().fold(0, |mask, pressed| (mask << 1) | *pressed as u16)
+    }
+
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.u16(self.mask());
+        out.bool(self.waiting);
+        out.bool(self.released.is_some());
+        out.u8(self.released.unwrap_or(0));
+        out.u16(self.held);
+    }
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, SnapshotError> {
+        let mut keypad = Self::new();
+        let mask = input.u16()?;
+        for (key, pressed) in keypad.keys.iter_mut().enumerate() {
+            *pressed = mask & (1 << key) != 0;
+        }
+        keypad.waiting = input.bool()?;
+        let released = input.bool()?;
+        let key = input.u8()?;
+        if key as usize >= keypad.keys.len() {
+            return Err(input.invalid());
+        }
+        keypad.released = if released { Some(key) } else { None };
+        keypad.held = if input.is_at_end() { 0 } else { input.u16()? };
+        Ok(keypad)
+    }
+}
+impl Default for Keypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_keys_the_keypad_does_not_have() {
+        let mut keypad = Keypad::new();
+        keypad.press(0xF);
+        keypad.press(0x10);
+        keypad.press(0xFF);
+        assert!(keypad.is_pressed(0xF));
+        assert!(!keypad.is_pressed(0x10) && !keypad.is_pressed(0xFF));
+
+        keypad.wait_for_key();
+        keypad.release(0x10);
+        assert!(keypad.is_blocking());
+        keypad.press(0xE);
+        keypad.release(0xE);
+        assert_eq!(keypad.wait_for_key(), Some(0xE));
+    }
+
+    #[test]
+    fn waits_for_keys_held_before_to_be_pressed_again() {
+        let mut keypad = Keypad::new();
+        keypad.press(0x5);
+        assert_eq!(keypad.wait_for_key(), None);
+        keypad.release(0x5);
+        assert!(keypad.is_blocking());
+
+        keypad.press(0x5);
+        keypad.release(0x5);
+        assert_eq!(keypad.wait_for_key(), Some(0x5));
+    }
+}
\ No newline at end of file
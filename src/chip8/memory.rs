@@ -1,7 +1,14 @@
 use crate::chip8::memory::MemoryError::*;
-use std::convert::TryFrom;
-use std::array::TryFromSliceError;
-use std::fmt::Write;
+use crate::chip8::snapshot::{SectionReader, SectionWriter, SnapshotError};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt::{self, Write};
+
+/// Size of the memory of the original interpreter.
+pub const DEFAULT_MEMORY_SIZE: usize = 0x1000;
+/// Largest supported memory size, the full 16-bit address space.
+pub const MAX_MEMORY_SIZE: usize = 0x10000;
 
 pub struct Memory {
     /// The raw data that makes up the system memory.
@@ -9,72 +16,249 @@ pub struct Memory {
     /// Memory map:<br>
     /// 0x000 - 0x1FF: Chip-8 Interpreter<br>
     /// 0x050 - 0x0A0: Used for the built-int 4x5 pixel font set (0-F).<br>
-    /// 0x200 - 0xFFF: Program ROM and Work RAM
-    data: [u8; 4096]
+    /// 0x200 - 0xFFF: Program ROM and Work RAM (up to 0xFFFF with 64 KiB of memory)
+    data: Vec<u8>,
+    /// Whether addresses past the end of memory wrap around to the start, instead of failing.
+    ///
+    /// With the default 4 KiB of memory this is the 12-bit wrapping of the original interpreter.
+    wrapping: bool,
+    /// The accesses made since tracing started, while it is on.
+    trace: RefCell<Option<Vec<Access>>>
 }
 impl Memory {
     pub fn new() -> Self {
+        Self::with_size(DEFAULT_MEMORY_SIZE)
+    }
+    /// Creates a memory of `size` bytes, clamped to `MAX_MEMORY_SIZE`.
+    pub fn with_size(size: usize) -> Self {
         Self {
-            data: [0; 4096]
+            data: vec![0; size.min(MAX_MEMORY_SIZE)],
+            wrapping: false,
+            trace: RefCell::new(None)
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_wrapping(&self) -> bool {
+        self.wrapping
+    }
+    pub fn set_wrapping(&mut self, wrapping: bool) {
+        self.wrapping = wrapping;
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.borrow().is_some()
+    }
+    /// Starts or stops recording the accesses to memory, discarding those recorded so far.
+    ///
+    /// Instruction fetches are not recorded.
+    pub fn set_tracing(&mut self, tracing: bool) {
+        *self.trace.get_mut() = if tracing { Some(Vec::new()) } else { None };
+    }
+    /// Takes the accesses recorded since the last call, in the order they were made.
+    pub fn take_accesses(&mut self) -> Vec<Access> {
+        self.trace.get_mut().as_mut().map(std::mem::take).unwrap_or_default()
+    }
+    /// Records an access of `len` bytes starting at `index`, split in two if it wraps around.
+    fn record(&self, index: usize, len: usize, kind: AccessKind) {
+        if let Some(trace) = self.trace.borrow_mut().as_mut() {
+            let first = len.min(self.data.len() - index);
+            trace.push(Access { addr: index as u16, len: first, kind });
+            if first < len {
+                trace.push(Access { addr: 0, len: len - first, kind });
+            }
+        }
+    }
+
+    /// Resolves an access of `len` bytes at `addr` to the index of its first byte.
+    fn index(&self, addr: u16, len: usize) -> Result<usize, MemoryError> {
+        let size = self.data.len();
+        let start = if self.wrapping && size > 0 { addr as usize % size } else { addr as usize };
+
+        if (self.wrapping && size > 0 && len <= size) || start + len <= size {
+            Ok(start)
+        } else {
+            Err(MemoryAccessError { addr: addr as usize, len })
         }
     }
 
-    pub fn read(&self, addr: u16) -> u8 {
-        self.data[addr as usize]
+    pub fn read(&self, addr: u16) -> Result<u8, MemoryError> {
+        let index = self.index(addr, 1)?;
+        self.record(index, 1, AccessKind::Read);
+        Ok(self.data[index])
     }
     pub fn read_16(&self, addr: u16) -> Result<u16, MemoryError> {
-        Ok(u16::from_be_bytes(<[u8;2]>::try_from(self.read_many(addr, 2))?))
+        let bytes = self.read_many(addr, 2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
     }
-    pub fn read_many(&self, addr: u16, len: u16) -> &[u8] {
-        self.read_range(addr, addr+len)
+    /// Reads the instruction word at `addr`, the same as `read_16` except that it is not traced.
+    pub fn fetch(&self, addr: u16) -> Result<u16, MemoryError> {
+        let start = self.index(addr, 2)?;
+        Ok(u16::from_be_bytes([self.data[start], self.data[(start + 1) % self.data.len()]]))
     }
-    pub fn read_range(&self, from: u16, to: u16) -> &[u8] {
-        &self.data[(from as usize)..(to as usize)]
+    /// Reads `len` bytes starting at `addr`.
+    ///
+    /// The bytes are borrowed straight from memory, unless the access wraps around the end.
+    pub fn read_many(&self, addr: u16, len: usize) -> Result<Cow<'_, [u8]>, MemoryError> {
+        let start = self.index(addr, len)?;
+        self.record(start, len, AccessKind::Read);
+
+        if start + len <= self.data.len() {
+            Ok(Cow::Borrowed(&self.data[start..(start + len)]))
+        } else {
+            let wrapped = len - (self.data.len() - start);
+            Ok(Cow::Owned([&self.data[start..], &self.data[..wrapped]].concat()))
+        }
+    }
+    /// Reads the bytes from `from` up to, but not including, `to`.
+    pub fn read_range(&self, from: u16, to: u16) -> Result<Cow<'_, [u8]>, MemoryError> {
+        if to < from {
+            return Err(MemoryAccessError { addr: from as usize, len: 0 });
+        }
+        self.read_many(from, (to - from) as usize)
     }
 
-    pub fn write(&mut self, addr: u16, val: u8) {
-        self.data[addr as usize] = val;
+    pub fn write(&mut self, addr: u16, val: u8) -> Result<(), MemoryError> {
+        let index = self.index(addr, 1)?;
+        self.record(index, 1, AccessKind::Write);
+        self.data[index] = val;
+        Ok(())
     }
-    pub fn copy(&mut self, addr: u16, val: &[u8]) {
+    /// Writes all of `val` starting at `addr`; nothing is written if any of it would be out of bounds.
+    pub fn copy(&mut self, addr: u16, val: &[u8]) -> Result<(), MemoryError> {
+        let start = self.index(addr, val.len())?;
+        let size = self.data.len();
+        self.record(start, val.len(), AccessKind::Write);
+
         for (i, mem) in val.iter().enumerate() {
-            self.data[addr as usize + i] = *mem;
+            self.data[(start + i) % size] = *mem;
         }
+        Ok(())
     }
 
     pub fn dump(&self) -> String {
         let mut writer = String::new();
-        writeln!(writer, "Length: {0} (0x{0:x}) bytes", self.data.len());
+        writeln!(writer, "Length: {0} (0x{0:x}) bytes", self.data.len()).unwrap();
 
         for (i, row) in self.data.chunks(0x10).enumerate() {
-            write!(writer, "{:04X}:  ", i * 0x10);
+            write!(writer, "{:04X}:  ", i * 0x10).unwrap();
 
             for (j, val) in row.iter().enumerate() {
                 write!(writer, "{:02X}{}", val, match j+1 {
                     0x10 => "",
                     _ => " "
-                });
+                }).unwrap();
             }
 
-            write!(writer, " ");
-            for (j, val) in row.iter().enumerate() {
+            write!(writer, " ").unwrap();
+            for val in row.iter() {
                 write!(writer, "{}", match char::from(*val) {
                     c if c.is_control() => { '.' },
-                    c @ _ => { c }
-                });
+                    c => { c }
+                }).unwrap();
             }
 
-            writeln!(writer, "");
+            writeln!(writer).unwrap();
         }
 
         writer
     }
+
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.bool(self.wrapping);
+        out.bytes(&self.data);
+    }
+    /// Reads the memory, with tracing off.
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, SnapshotError> {
+        let wrapping = input.bool()?;
+        let data = input.bytes()?;
+        if data.len() > MAX_MEMORY_SIZE {
+            return Err(input.invalid());
+        }
+
+        let mut memory = Self::with_size(data.len());
+        memory.data.copy_from_slice(data);
+        memory.wrapping = wrapping;
+        Ok(memory)
+    }
+}
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write
 }
 
+/// An access of *len* bytes of memory starting at *addr*, as recorded while tracing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub addr: u16,
+    pub len: usize,
+    pub kind: AccessKind
+}
+impl Access {
+    pub fn contains(&self, addr: u16) -> bool {
+        addr >= self.addr && ((addr - self.addr) as usize) < self.len
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemoryError {
-    MemoryAccessError
+    /// An access of *len* bytes starting at *addr* reached outside of memory.
+    MemoryAccessError { addr: usize, len: usize }
+}
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryAccessError { addr, len } => write!(f, "out of bounds memory access of {} bytes at {:#05X}", len, addr)
+        }
+    }
 }
-impl From<TryFromSliceError> for MemoryError {
-    fn from(_: TryFromSliceError) -> Self {
-        MemoryAccessError
+impl Error for MemoryError {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_accesses_out_of_bounds() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.read(0x1000), Err(MemoryAccessError { addr: 0x1000, len: 1 }));
+        assert_eq!(memory.read_16(0xFFF), Err(MemoryAccessError { addr: 0xFFF, len: 2 }));
+        assert_eq!(memory.read_range(0xFF0, 0x1001), Err(MemoryAccessError { addr: 0xFF0, len: 0x11 }));
+        assert_eq!(memory.read_range(0x300, 0x200), Err(MemoryAccessError { addr: 0x300, len: 0 }));
+        assert_eq!(memory.write(0xFFFF, 1), Err(MemoryAccessError { addr: 0xFFFF, len: 1 }));
+
+        // Nothing is written by a copy that doesn't fit.
+        assert_eq!(memory.copy(0xFFE, &[1, 2, 3]), Err(MemoryAccessError { addr: 0xFFE, len: 3 }));
+        assert_eq!(&*memory.read_range(0xFFE, 0x1000).unwrap(), &[0, 0]);
+        assert_eq!(memory.copy(0xFFD, &[1, 2, 3]), Ok(()));
+        assert_eq!(memory.read(0xFFF), Ok(3));
+    }
+
+    #[test]
+    fn wraps_around_if_asked_to() {
+        let mut memory = Memory::new();
+        memory.set_wrapping(true);
+
+        assert_eq!(memory.copy(0xFFE, &[1, 2, 3]), Ok(()));
+        assert_eq!(memory.read(0x000), Ok(3));
+        assert_eq!(memory.read(0x1FFF), Ok(2));
+        assert_eq!(memory.read_16(0xFFF), Ok(0x0203));
+        assert_eq!(&*memory.read_many(0xFFE, 3).unwrap(), &[1, 2, 3]);
+        assert_eq!(memory.write(0x1000, 4), Ok(()));
+        assert_eq!(memory.read(0x000), Ok(4));
+
+        // An access still can't be larger than memory.
+        assert!(memory.read_many(0, 0x1001).is_err());
     }
 }
\ No newline at end of file
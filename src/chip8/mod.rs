@@ -1,11 +1,64 @@
+#[allow(clippy::module_inception)]
 pub mod chip8;
 pub mod processor;
 pub mod opcode;
 pub mod memory;
 pub mod bios;
+pub mod display;
+pub mod keypad;
+pub mod timer;
+pub mod rng;
+pub mod quirks;
+pub mod variant;
+pub mod flags;
+pub mod audio;
+pub mod disassembler;
+pub mod assembler;
+pub mod debugger;
+pub mod checksum;
+pub mod deflate;
+pub mod snapshot;
+pub mod rewind;
+pub mod movie;
+pub mod trace;
+pub mod terminal;
+pub mod headless;
+pub mod screenshot;
+pub mod animation;
 
-pub use self::chip8::Chip8;
+pub use self::chip8::{Chip8, RunState};
 pub use self::processor::{Processor, Registers};
 pub use self::opcode::Opcode;
 pub use self::memory::Memory;
-pub use self::bios::Bios;
\ No newline at end of file
+pub use self::bios::Bios;
+pub use self::display::Display;
+pub use self::keypad::Keypad;
+pub use self::timer::Timers;
+pub use self::rng::{Rng, SplitMix64};
+pub use self::quirks::{Quirks, IndexIncrement};
+pub use self::variant::Variant;
+pub use self::flags::{RplFlags, FlagStorage, FileFlagStorage};
+pub use self::audio::Audio;
+pub use self::disassembler::{Disassembler, Disassembly};
+pub use self::assembler::{Assembler, Assembly};
+pub use self::debugger::Debugger;
+pub use self::rewind::Rewind;
+pub use self::movie::Movie;
+pub use self::trace::Tracer;
+pub use self::terminal::TerminalRenderer;
+pub use self::screenshot::{Screenshot, Camera};
+pub use self::animation::Recorder;
+
+/// The ROM of the assembly `source`, for `variant`.
+#[cfg(test)]
+pub(crate) fn rom(variant: Variant, source: &str) -> Vec<u8> {
+    Assembler::new(variant).assemble(source).unwrap().bytes
+}
+/// Boots a machine of `variant`, with its default quirks, running the assembly `source`.
+#[cfg(test)]
+pub(crate) fn boot(variant: Variant, source: &str) -> Chip8 {
+    let mut system = Chip8::with_variant(variant, variant.quirks());
+    system.init().unwrap();
+    system.bios.load_rom(&mut system.memory, &rom(variant, source)).unwrap();
+    system
+}
\ No newline at end of file
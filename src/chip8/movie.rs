@@ -0,0 +1,410 @@
+//! Recordings of the input of a run, played back to reproduce it exactly.
+//!
+//! A movie is a text file, made of a header describing the machine the run started on, followed
+//! by the events of the run, in order of frame:
+//!
+//! ```text
+//! emul8 movie 1
+//! variant chip8
+//! quirks shift-vy,load-store,no-jump-vx,vf-reset,clip,display-wait
+//! clock 600
+//! seed 0x2545F4914F6CDD1D
+//! rom 0x8F1D3A27
+//! hash-interval 60
+//! frames 300
+//! final-hash 0x9E3779B9
+//!
+//! 0 hash 0x5BD1E995
+//! 42 keys 5
+//! 48 keys 5A
+//! 50 keys -
+//! 60 hash 0x1B873593
+//! ...
+//! ```
+//!
+//! `rom` is the CRC-32 of the ROM the run started from, `seed` the seed of the random number
+//! generator, and `frames` the number of frames the run lasted, counting a frame it stopped
+//! partway through. A `keys` event gives the keys held from the start of a frame on, as
+//! hexadecimal digits, or `-` for none; they are only written when they change. A `hash` event
+//! gives the CRC-32 of the save state of the machine at the start of a frame, after its keys are
+//! applied, and is written every `hash-interval` frames; `final-hash` is the same for the state the
+//! run ended in. A state that differs on playback means the run has desynchronised. Blank lines
+//! and lines starting with `#` are ignored.
+
+use crate::chip8::{Chip8, Quirks, Variant};
+use crate::chip8::checksum::crc32;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+use self::MovieError::*;
+
+/// Version of the format written.
+pub const MOVIE_VERSION: u32 = 1;
+/// Number of frames between the state hashes written by default.
+pub const DEFAULT_HASH_INTERVAL: u64 = 60;
+
+/// The input of a run, along with everything else needed to reproduce it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub clock_speed: u32,
+    pub seed: u64,
+    /// CRC-32 of the ROM.
+    pub rom_hash: u32,
+    /// Number of frames between state hashes.
+    pub hash_interval: u64,
+    frames: u64,
+    /// Keys held from a frame on, as a mask with a bit per key, at every frame they change.
+    inputs: BTreeMap<u64, u16>,
+    /// Hashes of the state of the machine at the start of frames.
+    hashes: BTreeMap<u64, u32>,
+    /// Hash of the state the run ended in, once the recording is finished.
+    final_hash: Option<u32>
+}
+impl Movie {
+    /// Starts an empty recording of a run of `rom`.
+    pub fn new(variant: Variant, quirks: Quirks, clock_speed: u32, seed: u64, rom: &[u8]) -> Self {
+        Self {
+            variant,
+            quirks,
+            clock_speed,
+            seed,
+            rom_hash: crc32(rom),
+            hash_interval: DEFAULT_HASH_INTERVAL,
+            frames: 0,
+            inputs: BTreeMap::new(),
+            hashes: BTreeMap::new(),
+            final_hash: None
+        }
+    }
+
+    /// Number of frames the run lasted.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+    /// The frames the held keys change at, along with the keys held from then on.
+    pub fn inputs(&self) -> impl Iterator<Item = (u64, u16)> + '_ {
+        self.inputs.iter().map(|(frame, keys)| (*frame, *keys))
+    }
+
+    /// Creates the machine the run started on, with `rom` loaded, which must be the one recorded.
+    pub fn boot(&self, rom: &[u8]) -> Result<Chip8, Box<dyn Error>> {
+        let actual = crc32(rom);
+        if actual != self.rom_hash {
+            return Err(Box::new(RomMismatchError { expected: self.rom_hash, actual }));
+        }
+
+        let mut system = Chip8::with_variant(self.variant, self.quirks);
+        system.timers.set_clock_speed(self.clock_speed);
+        system.seed(self.seed);
+        system.init()?;
+        system.bios.load_rom(&mut system.memory, rom)?;
+        Ok(system)
+    }
+
+    /// Records the keys held at the start of the frame `system` is about to run.
+    pub fn record(&mut self, system: &Chip8) {
+        let frame = system.timers.frames();
+        let keys = held_keys(system);
+        let last = self.inputs.values().next_back().copied().unwrap_or(0);
+        if keys != last {
+            self.inputs.insert(frame, keys);
+        }
+        if frame % self.hash_interval == 0 {
+            self.hashes.insert(frame, state_hash(system));
+        }
+        self.frames = frame + 1;
+    }
+    /// Ends the recording with the final state of `system`.
+    pub fn finish_recording(&mut self, system: &Chip8) {
+        self.final_hash = Some(state_hash(system));
+    }
+
+    /// Applies the keys held at the start of the frame `system` is about to run, checking its state
+    /// against the recording.
+    pub fn play(&self, system: &mut Chip8) -> Result<(), MovieError> {
+        let frame = system.timers.frames();
+        if let Some(keys) = self.inputs.get(&frame) {
+            for key in 0..16 {
+                match (keys & (1 << key) != 0, system.keypad.is_pressed(key)) {
+                    (true, false) => system.keypad.press(key),
+                    (false, true) => system.keypad.release(key),
+                    _ => { }
+                }
+            }
+        }
+        self.check(system, frame)
+    }
+    /// Checks that `system` ended up in the state the recording ended in, once played back for
+    /// `frames()` frames.
+    pub fn finish_playback(&self, system: &Chip8) -> Result<(), MovieError> {
+        check(system, self.final_hash)
+    }
+
+    fn check(&self, system: &Chip8, frame: u64) -> Result<(), MovieError> {
+        check(system, self.hashes.get(&frame).copied())
+    }
+
+    pub fn save_file(&self, file: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(file, self.to_string())?;
+        Ok(())
+    }
+    pub fn load_file(file: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(fs::read_to_string(file)?.parse()?)
+    }
+}
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "emul8 movie {}", MOVIE_VERSION)?;
+        writeln!(f, "variant {}", self.variant)?;
+        writeln!(f, "quirks {}", self.quirks)?;
+        writeln!(f, "clock {}", self.clock_speed)?;
+        writeln!(f, "seed {:#018X}", self.seed)?;
+        writeln!(f, "rom {:#010X}", self.rom_hash)?;
+        writeln!(f, "hash-interval {}", self.hash_interval)?;
+        writeln!(f, "frames {}", self.frames)?;
+        if let Some(hash) = self.final_hash {
+            writeln!(f, "final-hash {:#010X}", hash)?;
+        }
+        writeln!(f)?;
+
+        let mut hashes = self.hashes.iter().peekable();
+        for (frame, keys) in &self.inputs {
+            while let Some((hash_frame, hash)) = hashes.next_if(|(hash_frame, _)| *hash_frame < frame) {
+                writeln!(f, "{} hash {:#010X}", hash_frame, hash)?;
+            }
+            let keys: String = (0..16).filter(|key| keys & (1 << key) != 0).map(|key| format!("{:X}", key)).collect();
+            writeln!(f, "{} keys {}", frame, if keys.is_empty() { "-" } else { &keys })?;
+        }
+        for (frame, hash) in hashes {
+            writeln!(f, "{} hash {:#010X}", frame, hash)?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next().and_then(|(_, line)| line.strip_prefix("emul8 movie ")) {
+            Some(version) => match version.parse() {
+                Ok(MOVIE_VERSION) => { },
+                Ok(version) => return Err(UnsupportedVersionError { version }),
+                Err(_) => return Err(InvalidHeaderError)
+            },
+            None => return Err(InvalidHeaderError)
+        }
+
+        let (mut variant, mut quirks, mut clock_speed, mut seed, mut rom_hash, mut hash_interval, mut frames) =
+            (None, None, None, None, None, None, None);
+        let mut final_hash = None;
+        let mut inputs = BTreeMap::new();
+        let mut hashes = BTreeMap::new();
+        for (line, text) in lines {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let syntax = |message: String| SyntaxError { line, message };
+            let value = |word: &str| parse_number(word).ok_or_else(|| syntax(format!("invalid number `{}`", word)));
+
+            match words.as_slice() {
+                ["variant", name] => variant = Some(name.parse().map_err(syntax)?),
+                ["quirks", list] => quirks = Some(Quirks::default().apply_list(list).map_err(syntax)?),
+                ["clock", hz] => clock_speed = Some(u32::try_from(value(hz)?).map_err(|_| syntax(format!("invalid clock speed `{}`", hz)))?),
+                ["seed", number] => seed = Some(value(number)?),
+                ["rom", hash] => rom_hash = Some(u32::try_from(value(hash)?).map_err(|_| syntax(format!("invalid hash `{}`", hash)))?),
+                ["hash-interval", interval] => hash_interval = Some(value(interval)?.max(1)),
+                ["frames", count] => frames = Some(value(count)?),
+                ["final-hash", hash] => final_hash = Some(u32::try_from(value(hash)?).map_err(|_| syntax(format!("invalid hash `{}`", hash)))?),
+                [frame, "keys", keys] => {
+                    let mut mask = 0;
+                    if *keys != "-" {
+                        for key in keys.chars() {
+                            let key = key.to_digit(16).ok_or_else(|| syntax(format!("invalid key `{}`", key)))?;
+                            mask |= 1 << key;
+                        }
+                    }
+                    inputs.insert(value(frame)?, mask);
+                },
+                [frame, "hash", hash] => {
+                    let hash = u32::try_from(value(hash)?).map_err(|_| syntax(format!("invalid hash `{}`", hash)))?;
+                    hashes.insert(value(frame)?, hash);
+                },
+                _ => return Err(syntax(format!("unexpected `{}`", text)))
+            }
+        }
+
+        Ok(Self {
+            variant: variant.ok_or(MissingFieldError { name: "variant" })?,
+            quirks: quirks.ok_or(MissingFieldError { name: "quirks" })?,
+            clock_speed: clock_speed.ok_or(MissingFieldError { name: "clock" })?,
+            seed: seed.ok_or(MissingFieldError { name: "seed" })?,
+            rom_hash: rom_hash.ok_or(MissingFieldError { name: "rom" })?,
+            hash_interval: hash_interval.unwrap_or(DEFAULT_HASH_INTERVAL),
+            frames: frames.ok_or(MissingFieldError { name: "frames" })?,
+            inputs,
+            hashes,
+            final_hash
+        })
+    }
+}
+
+/// Checks the state of `system` against the `expected` hash, if any.
+fn check(system: &Chip8, expected: Option<u32>) -> Result<(), MovieError> {
+    match expected {
+        Some(expected) => {
+            let actual = state_hash(system);
+            if actual != expected {
+                return Err(DesyncError { frame: system.timers.frames(), expected, actual });
+            }
+            Ok(())
+        },
+        None => Ok(())
+    }
+}
+
+/// The keys held on the keypad of `system`, as a mask with a bit per key.
+fn held_keys(system: &Chip8) -> u16 {
+    (0..16).filter(|key| system.keypad.is_pressed(*key)).fold(0, |mask, key| mask | (1 << key))
+}
+
+/// The hash of the whole state of `system`, compared to detect desynchronisation.
+pub fn state_hash(system: &Chip8) -> u32 {
+    crc32(&system.save_state())
+}
+
+/// Parses a decimal, or `0x` prefixed hexadecimal number.
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// The file doesn't start with the line `emul8 movie <version>`.
+    InvalidHeaderError,
+    UnsupportedVersionError { version: u32 },
+    SyntaxError { line: usize, message: String },
+    MissingFieldError { name: &'static str },
+    /// The ROM played back isn't the one recorded.
+    RomMismatchError { expected: u32, actual: u32 },
+    /// The state of the machine differs from the one recorded in `frame`.
+    DesyncError { frame: u64, expected: u32, actual: u32 }
+}
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidHeaderError => write!(f, "not a movie file"),
+            UnsupportedVersionError { version } => write!(f, "unsupported movie version {}", version),
+            SyntaxError { line, message } => write!(f, "line {}: {}", line, message),
+            MissingFieldError { name } => write!(f, "missing `{}` in the header", name),
+            RomMismatchError { expected, actual } =>
+                write!(f, "the movie was recorded with another ROM (hash {:#010X}, not {:#010X})", expected, actual),
+            DesyncError { frame, expected, actual } =>
+                write!(f, "desynchronised at frame {}: state hash {:#010X}, expected {:#010X}", frame, actual, expected)
+        }
+    }
+}
+impl Error for MovieError { }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8;
+
+    /// A program whose state depends on the random numbers and the keypad; the machine is booted
+    /// by the movie.
+    fn rom() -> Vec<u8> {
+        chip8::rom(Variant::Chip8, "
+            : main
+                v1 := 5
+                loop
+                    v0 := random 0xFF
+                    if v1 key then v2 += 1
+                    i := 0x300
+                    save v2
+                again
+        ")
+    }
+
+    /// Records a run of 40 frames, holding key 5 for some of them.
+    fn record() -> (Movie, Vec<u8>) {
+        let rom = rom();
+        let mut movie = Movie::new(Variant::Chip8, Quirks::xo_chip(), 300, 42, &rom);
+        movie.hash_interval = 10;
+
+        let mut system = movie.boot(&rom).unwrap();
+        for frame in 0..40 {
+            match frame {
+                5 => system.keypad.press(5),
+                15 => system.keypad.release(5),
+                _ => { }
+            }
+            movie.record(&system);
+            system.run_frame().unwrap();
+        }
+        movie.finish_recording(&system);
+        (movie, system.save_state())
+    }
+
+    fn play(movie: &Movie) -> Result<Vec<u8>, MovieError> {
+        let mut system = movie.boot(&rom()).unwrap();
+        for _ in 0..movie.frames() {
+            movie.play(&mut system)?;
+            system.run_frame().unwrap();
+        }
+        movie.finish_playback(&system)?;
+        Ok(system.save_state())
+    }
+
+    #[test]
+    fn plays_back_to_the_same_state() {
+        let (movie, state) = record();
+        assert_eq!(movie.frames(), 40);
+        assert_eq!(movie.inputs().collect::<Vec<_>>(), vec![(5, 0x0020), (15, 0x0000)]);
+
+        let text = movie.to_string();
+        assert!(text.contains("\nquirks shift-vy,load-store,no-jump-vx,no-vf-reset,no-clip,no-display-wait\n"));
+        assert!(text.contains("\n5 keys 5\n10 hash "));
+        assert!(text.contains("\n15 keys -\n20 hash "));
+
+        let parsed: Movie = text.parse().unwrap();
+        assert_eq!(parsed, movie);
+        assert_eq!(play(&parsed), Ok(state));
+    }
+
+    #[test]
+    fn detects_desynchronisation() {
+        let (movie, _) = record();
+
+        let text = movie.to_string().replace("\n15 keys -\n", "\n16 keys -\n");
+        match play(&text.parse().unwrap()) {
+            Err(DesyncError { frame: 20, .. }) => { },
+            result => panic!("expected a desync at frame 20, got {:?}", result.map(|_| ()))
+        }
+
+        let mut shortened = movie.clone();
+        shortened.frames -= 1;
+        assert!(matches!(play(&shortened), Err(DesyncError { frame: 39, .. })));
+
+        assert!(movie.boot(&[0x00, 0xE0]).err().unwrap().to_string().contains("another ROM"));
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert_eq!("emul8 save\n".parse::<Movie>(), Err(InvalidHeaderError));
+        assert_eq!("emul8 movie 2\n".parse::<Movie>(), Err(UnsupportedVersionError { version: 2 }));
+        assert_eq!("emul8 movie 1\nvariant chip8\n".parse::<Movie>(), Err(MissingFieldError { name: "quirks" }));
+
+        let text = record().0.to_string().replace("\n5 keys 5\n", "\n5 keys 5G\n");
+        assert_eq!(text.parse::<Movie>(), Err(SyntaxError { line: 12, message: "invalid key `G`".to_string() }));
+    }
+}
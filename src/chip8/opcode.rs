@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcode {
     /// __0*nnn* - SYS *addr*__
     ///
@@ -12,6 +14,30 @@ pub enum Opcode {
     ///
     /// Return from a subroutine.
     _00EE,
+    /// __00C*n* - SCD *nibble*__ (SUPER-CHIP)
+    ///
+    /// Scroll the display down by *n* lines.
+    _00Cn { n: u8 },
+    /// __00FB - SCR__ (SUPER-CHIP)
+    ///
+    /// Scroll the display right by 4 pixels.
+    _00FB,
+    /// __00FC - SCL__ (SUPER-CHIP)
+    ///
+    /// Scroll the display left by 4 pixels.
+    _00FC,
+    /// __00FD - EXIT__ (SUPER-CHIP)
+    ///
+    /// Exit the interpreter.
+    _00FD,
+    /// __00FE - LOW__ (SUPER-CHIP)
+    ///
+    /// Switch to the 64x32 low resolution mode.
+    _00FE,
+    /// __00FF - HIGH__ (SUPER-CHIP)
+    ///
+    /// Switch to the 128x64 high resolution mode.
+    _00FF,
     /// __1*nnn* - JP *addr*__
     ///
     /// Jump to location at *nnn*.
@@ -32,6 +58,14 @@ pub enum Opcode {
     ///
     /// Skip the next instruction if V*x* == V*y*.
     _5xy0 { x: u8, y: u8 },
+    /// __5*xy*2 - LD [I], V*x*-V*y*__ (XO-CHIP)
+    ///
+    /// Store registers V*x* through V*y* in memory starting at location I.
+    _5xy2 { x: u8, y: u8 },
+    /// __5*xy*3 - LD V*x*-V*y*, [I]__ (XO-CHIP)
+    ///
+    /// Read registers V*x* through V*y* from memory starting at location I.
+    _5xy3 { x: u8, y: u8 },
     /// __6*xkk* - LD V*x*, *byte*__
     ///
     /// Set V*x* = *kk*.
@@ -95,6 +129,8 @@ pub enum Opcode {
     /// __D*xyn* - DRW V*x*, V*y*, *nibble*__
     ///
     /// Display *n*-byte sprite starting at memory location I at (V*x*, V*y*), set VF = *collision*.
+    ///
+    /// On SUPER-CHIP, D*xy*0 displays a 16x16 sprite of 32 bytes instead.
     _Dxyn { x: u8, y: u8, n: u8 },
     /// __E*x*9E - SKP V*x*__
     ///
@@ -104,6 +140,18 @@ pub enum Opcode {
     ///
     /// Skip the next instruction if key with the value of V*x* is not pressed.
     _ExA1 { x: u8 },
+    /// __F000 *nnnn* - LD I, long *addr*__ (XO-CHIP)
+    ///
+    /// Set I = *nnnn*, taken from the word following the instruction.
+    _F000 { n: u16 },
+    /// __F*n*01 - PLANE *n*__ (XO-CHIP)
+    ///
+    /// Select the bitplanes *n* for drawing, clearing and scrolling.
+    _Fn01 { n: u8 },
+    /// __F002 - AUDIO__ (XO-CHIP)
+    ///
+    /// Load the 16-byte audio pattern buffer from memory starting at location I.
+    _F002,
     /// __F*x*07 - LD V*x*, DT__
     ///
     /// Set V*x* = *delay timer*.
@@ -128,6 +176,14 @@ pub enum Opcode {
     ///
     /// Set I = location for sprite for digit V*x*.
     _Fx29 { x: u8 },
+    /// __F*x*30 - LD HF, V*x*__ (SUPER-CHIP)
+    ///
+    /// Set I = location for 10-byte sprite for digit V*x*.
+    _Fx30 { x: u8 },
+    /// __F*x*3A - LD PITCH, V*x*__ (XO-CHIP)
+    ///
+    /// Set *pitch* = V*x*.
+    _Fx3A { x: u8 },
     /// __F*x*33 - LD B, V*x*__
     ///
     /// Store BCD representation of V*x* in memory locations I, I+1, and I+2.
@@ -140,22 +196,46 @@ pub enum Opcode {
     ///
     /// Read registers V0 through V*x* from memory starting at location I.
     _Fx65 { x: u8 },
+    /// __F*x*75 - LD R, V*x*__ (SUPER-CHIP)
+    ///
+    /// Store registers V0 through V*x* in the RPL user flags.
+    _Fx75 { x: u8 },
+    /// __F*x*85 - LD V*x*, R__ (SUPER-CHIP)
+    ///
+    /// Read registers V0 through V*x* from the RPL user flags.
+    _Fx85 { x: u8 },
     /// Invalid opcode.
     Invalid { code: u16 }
 }
 impl Opcode {
+    /// Decodes a single instruction word.
+    ///
+    /// The four-byte `LD I, long addr` decodes with an address of zero; use
+    /// [`from_long`](Opcode::from_long) to decode it along with the word that follows it.
     pub fn from(instruction: u16) -> Opcode {
         match instruction {
             0x00E0 => Opcode::_00E0,
             0x00EE => Opcode::_00EE,
+            0x00FB => Opcode::_00FB,
+            0x00FC => Opcode::_00FC,
+            0x00FD => Opcode::_00FD,
+            0x00FE => Opcode::_00FE,
+            0x00FF => Opcode::_00FF,
+            0xF000 => Opcode::_F000 { n: 0x0000 },
+            0xF002 => Opcode::_F002,
             _ => match instruction & 0xF000 {
-                0x0000 => Opcode::_0nnn { n: instruction & 0x0FFF },
+                0x0000 => match instruction & 0xFFF0 {
+                    0x00C0 => Opcode::_00Cn { n: (instruction & 0x000F) as u8 },
+                    _ => Opcode::_0nnn { n: instruction & 0x0FFF }
+                },
                 0x1000 => Opcode::_1nnn { n: instruction & 0x0FFF },
                 0x2000 => Opcode::_2nnn { n: instruction & 0x0FFF },
                 0x3000 => Opcode::_3xkk { x: ((instruction & 0x0F00) >> 8) as u8, k: (instruction & 0x00FF) as u8 },
                 0x4000 => Opcode::_4xkk { x: ((instruction & 0x0F00) >> 8) as u8, k: (instruction & 0x00FF) as u8 },
                 0x5000 => match instruction & 0x000F {
                     0x0000 => Opcode::_5xy0 { x: ((instruction & 0x0F00) >> 8) as u8, y: ((instruction & 0x00F0) >> 4) as u8 },
+                    0x0002 => Opcode::_5xy2 { x: ((instruction & 0x0F00) >> 8) as u8, y: ((instruction & 0x00F0) >> 4) as u8 },
+                    0x0003 => Opcode::_5xy3 { x: ((instruction & 0x0F00) >> 8) as u8, y: ((instruction & 0x00F0) >> 4) as u8 },
                     _ => Opcode::Invalid { code: instruction }
                 },
                 0x6000 => Opcode::_6xkk { x: ((instruction & 0x0F00) >> 8) as u8, k: (instruction & 0x00FF) as u8 },
@@ -186,19 +266,215 @@ impl Opcode {
                     _ => Opcode::Invalid { code: instruction }
                 },
                 0xF000 => match instruction & 0x00FF {
+                    0x0001 => Opcode::_Fn01 { n: ((instruction & 0x0F00) >> 8) as u8 },
                     0x0007 => Opcode::_Fx07 { x: ((instruction & 0x0F00) >> 8) as u8 },
                     0x000A => Opcode::_Fx0A { x: ((instruction & 0x0F00) >> 8) as u8 },
                     0x0015 => Opcode::_Fx15 { x: ((instruction & 0x0F00) >> 8) as u8 },
                     0x0018 => Opcode::_Fx18 { x: ((instruction & 0x0F00) >> 8) as u8 },
                     0x001E => Opcode::_Fx1E { x: ((instruction & 0x0F00) >> 8) as u8 },
                     0x0029 => Opcode::_Fx29 { x: ((instruction & 0x0F00) >> 8) as u8 },
+                    0x0030 => Opcode::_Fx30 { x: ((instruction & 0x0F00) >> 8) as u8 },
+                    0x003A => Opcode::_Fx3A { x: ((instruction & 0x0F00) >> 8) as u8 },
                     0x0033 => Opcode::_Fx33 { x: ((instruction & 0x0F00) >> 8) as u8 },
                     0x0055 => Opcode::_Fx55 { x: ((instruction & 0x0F00) >> 8) as u8 },
                     0x0065 => Opcode::_Fx65 { x: ((instruction & 0x0F00) >> 8) as u8 },
+                    0x0075 => Opcode::_Fx75 { x: ((instruction & 0x0F00) >> 8) as u8 },
+                    0x0085 => Opcode::_Fx85 { x: ((instruction & 0x0F00) >> 8) as u8 },
                     _ => Opcode::Invalid { code: instruction }
                 },
                 _ => Opcode::Invalid { code: instruction }
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Decodes an instruction word along with the word that follows it in memory, which is only
+    /// part of the instruction for `LD I, long addr`.
+    pub fn from_long(instruction: u16, next: u16) -> Opcode {
+        match Opcode::from(instruction) {
+            Opcode::_F000 { .. } => Opcode::_F000 { n: next },
+            operation => operation
+        }
+    }
+
+    /// Length of the instruction in bytes.
+    pub fn size(&self) -> u16 {
+        match self {
+            Opcode::_F000 { .. } => 4,
+            _ => 2
+        }
+    }
+
+    /// Encodes the instruction back into its instruction word, so that
+    /// `Opcode::from(operation.encode()) == operation`.
+    ///
+    /// Operands are masked to the width of their field. For the four-byte `LD I, long addr` this is
+    /// only the first word; see [`encode_bytes`](Opcode::encode_bytes).
+    pub fn encode(&self) -> u16 {
+        let xkk = |base: u16, x: u8, k: u8| base | ((x as u16 & 0xF) << 8) | k as u16;
+        let xy = |base: u16, x: u8, y: u8| base | ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4);
+        let x = |base: u16, x: u8| base | ((x as u16 & 0xF) << 8);
+
+        match *self {
+            Opcode::_0nnn { n } => n & 0x0FFF,
+            Opcode::_00E0 => 0x00E0,
+            Opcode::_00EE => 0x00EE,
+            Opcode::_00Cn { n } => 0x00C0 | (n as u16 & 0xF),
+            Opcode::_00FB => 0x00FB,
+            Opcode::_00FC => 0x00FC,
+            Opcode::_00FD => 0x00FD,
+            Opcode::_00FE => 0x00FE,
+            Opcode::_00FF => 0x00FF,
+            Opcode::_1nnn { n } => 0x1000 | (n & 0x0FFF),
+            Opcode::_2nnn { n } => 0x2000 | (n & 0x0FFF),
+            Opcode::_3xkk { x, k } => xkk(0x3000, x, k),
+            Opcode::_4xkk { x, k } => xkk(0x4000, x, k),
+            Opcode::_5xy0 { x, y } => xy(0x5000, x, y),
+            Opcode::_5xy2 { x, y } => xy(0x5002, x, y),
+            Opcode::_5xy3 { x, y } => xy(0x5003, x, y),
+            Opcode::_6xkk { x, k } => xkk(0x6000, x, k),
+            Opcode::_7xkk { x, k } => xkk(0x7000, x, k),
+            Opcode::_8xy0 { x, y } => xy(0x8000, x, y),
+            Opcode::_8xy1 { x, y } => xy(0x8001, x, y),
+            Opcode::_8xy2 { x, y } => xy(0x8002, x, y),
+            Opcode::_8xy3 { x, y } => xy(0x8003, x, y),
+            Opcode::_8xy4 { x, y } => xy(0x8004, x, y),
+            Opcode::_8xy5 { x, y } => xy(0x8005, x, y),
+            Opcode::_8xy6 { x, y } => xy(0x8006, x, y),
+            Opcode::_8xy7 { x, y } => xy(0x8007, x, y),
+            Opcode::_8xyE { x, y } => xy(0x800E, x, y),
+            Opcode::_9xy0 { x, y } => xy(0x9000, x, y),
+            Opcode::_Annn { n } => 0xA000 | (n & 0x0FFF),
+            Opcode::_Bnnn { n } => 0xB000 | (n & 0x0FFF),
+            Opcode::_Cxkk { x, k } => xkk(0xC000, x, k),
+            Opcode::_Dxyn { x, y, n } => xy(0xD000, x, y) | (n as u16 & 0xF),
+            Opcode::_Ex9E { x: vx } => x(0xE09E, vx),
+            Opcode::_ExA1 { x: vx } => x(0xE0A1, vx),
+            Opcode::_F000 { .. } => 0xF000,
+            Opcode::_Fn01 { n } => x(0xF001, n),
+            Opcode::_F002 => 0xF002,
+            Opcode::_Fx07 { x: vx } => x(0xF007, vx),
+            Opcode::_Fx0A { x: vx } => x(0xF00A, vx),
+            Opcode::_Fx15 { x: vx } => x(0xF015, vx),
+            Opcode::_Fx18 { x: vx } => x(0xF018, vx),
+            Opcode::_Fx1E { x: vx } => x(0xF01E, vx),
+            Opcode::_Fx29 { x: vx } => x(0xF029, vx),
+            Opcode::_Fx30 { x: vx } => x(0xF030, vx),
+            Opcode::_Fx3A { x: vx } => x(0xF03A, vx),
+            Opcode::_Fx33 { x: vx } => x(0xF033, vx),
+            Opcode::_Fx55 { x: vx } => x(0xF055, vx),
+            Opcode::_Fx65 { x: vx } => x(0xF065, vx),
+            Opcode::_Fx75 { x: vx } => x(0xF075, vx),
+            Opcode::_Fx85 { x: vx } => x(0xF085, vx),
+            Opcode::Invalid { code } => code
+        }
+    }
+
+    /// Encodes the whole instruction as it is laid out in memory, including the address word of
+    /// `LD I, long addr`, so that `Opcode::from_long` decodes it back into `self`.
+    pub fn encode_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Opcode::_F000 { n } = *self {
+            bytes.extend_from_slice(&n.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Formats the instruction in assembly, formatting addresses with `address`.
+    ///
+    /// This allows addresses to be replaced by labels; the [`Display`](fmt::Display) implementation
+    /// formats them in hexadecimal.
+    pub fn to_asm<F: Fn(u16) -> String>(&self, address: F) -> String {
+        match *self {
+            Opcode::_0nnn { n } => format!("SYS {}", address(n)),
+            Opcode::_00E0 => "CLS".to_string(),
+            Opcode::_00EE => "RET".to_string(),
+            Opcode::_00Cn { n } => format!("SCD {}", n),
+            Opcode::_00FB => "SCR".to_string(),
+            Opcode::_00FC => "SCL".to_string(),
+            Opcode::_00FD => "EXIT".to_string(),
+            Opcode::_00FE => "LOW".to_string(),
+            Opcode::_00FF => "HIGH".to_string(),
+            Opcode::_1nnn { n } => format!("JP {}", address(n)),
+            Opcode::_2nnn { n } => format!("CALL {}", address(n)),
+            Opcode::_3xkk { x, k } => format!("SE V{:X}, {:#04X}", x, k),
+            Opcode::_4xkk { x, k } => format!("SNE V{:X}, {:#04X}", x, k),
+            Opcode::_5xy0 { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            Opcode::_5xy2 { x, y } => format!("LD [I], V{:X}-V{:X}", x, y),
+            Opcode::_5xy3 { x, y } => format!("LD V{:X}-V{:X}, [I]", x, y),
+            Opcode::_6xkk { x, k } => format!("LD V{:X}, {:#04X}", x, k),
+            Opcode::_7xkk { x, k } => format!("ADD V{:X}, {:#04X}", x, k),
+            Opcode::_8xy0 { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Opcode::_8xy1 { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            Opcode::_8xy2 { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Opcode::_8xy3 { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            Opcode::_8xy4 { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Opcode::_8xy5 { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            Opcode::_8xy6 { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            Opcode::_8xy7 { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            Opcode::_8xyE { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            Opcode::_9xy0 { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            Opcode::_Annn { n } => format!("LD I, {}", address(n)),
+            Opcode::_Bnnn { n } => format!("JP V0, {}", address(n)),
+            Opcode::_Cxkk { x, k } => format!("RND V{:X}, {:#04X}", x, k),
+            Opcode::_Dxyn { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            Opcode::_Ex9E { x } => format!("SKP V{:X}", x),
+            Opcode::_ExA1 { x } => format!("SKNP V{:X}", x),
+            Opcode::_F000 { n } => format!("LD I, long {}", address(n)),
+            Opcode::_Fn01 { n } => format!("PLANE {}", n),
+            Opcode::_F002 => "AUDIO".to_string(),
+            Opcode::_Fx07 { x } => format!("LD V{:X}, DT", x),
+            Opcode::_Fx0A { x } => format!("LD V{:X}, K", x),
+            Opcode::_Fx15 { x } => format!("LD DT, V{:X}", x),
+            Opcode::_Fx18 { x } => format!("LD ST, V{:X}", x),
+            Opcode::_Fx1E { x } => format!("ADD I, V{:X}", x),
+            Opcode::_Fx29 { x } => format!("LD F, V{:X}", x),
+            Opcode::_Fx30 { x } => format!("LD HF, V{:X}", x),
+            Opcode::_Fx3A { x } => format!("LD PITCH, V{:X}", x),
+            Opcode::_Fx33 { x } => format!("LD B, V{:X}", x),
+            Opcode::_Fx55 { x } => format!("LD [I], V{:X}", x),
+            Opcode::_Fx65 { x } => format!("LD V{:X}, [I]", x),
+            Opcode::_Fx75 { x } => format!("LD R, V{:X}", x),
+            Opcode::_Fx85 { x } => format!("LD V{:X}, R", x),
+            Opcode::Invalid { code } => format!("DW {:#06X}", code)
+        }
+    }
+}
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_asm(|addr| format!("{:#05X}", addr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_is_inverse_of_decode_for_every_word() {
+        for instruction in 0..=0xFFFF {
+            let operation = Opcode::from(instruction);
+            assert_eq!(operation.encode(), instruction, "{:?} encodes to a different word", operation);
+            assert_eq!(Opcode::from(operation.encode()), operation);
+        }
+    }
+
+    #[test]
+    fn decode_is_inverse_of_encode_for_every_long_load() {
+        for n in 0..=0xFFFF {
+            let operation = Opcode::_F000 { n };
+            let bytes = operation.encode_bytes();
+            assert_eq!(bytes, vec![0xF0, 0x00, (n >> 8) as u8, n as u8]);
+            assert_eq!(Opcode::from_long(u16::from_be_bytes([bytes[0], bytes[1]]), u16::from_be_bytes([bytes[2], bytes[3]])), operation);
+        }
+    }
+
+    #[test]
+    fn every_variant_is_decoded() {
+        let mut decoded = std::collections::HashSet::new();
+        for instruction in 0..=0xFFFF {
+            decoded.insert(std::mem::discriminant(&Opcode::from(instruction)));
+        }
+        // Every variant of `Opcode`, `Invalid` included.
+        assert_eq!(decoded.len(), 51);
+    }
+}
@@ -1,10 +1,12 @@
-use crate::chip8::{Chip8, Opcode};
+use crate::chip8::{Chip8, Opcode, Variant, Disassembler};
+use crate::chip8::audio::PATTERN_SIZE;
+use crate::chip8::bios::{FONT_ADDR, LARGE_FONT_ADDR};
 use crate::chip8::memory::MemoryError;
-use crate::chip8::memory::MemoryError::*;
+use crate::chip8::snapshot::{SectionReader, SectionWriter, SnapshotError};
 use crate::chip8::processor::ProcessorError::*;
-use std::convert::TryFrom;
-use std::array::TryFromSliceError;
-use std::fmt::Write;
+use log::{trace, warn};
+use std::error::Error;
+use std::fmt::{self, Write};
 
 pub struct Processor {
     operation: Opcode,
@@ -19,43 +21,85 @@ impl Processor {
     }
 
     fn execute_from(system: &mut Chip8, instruction: u16) -> Result<(), ProcessorError> {
-        Processor::execute(system, Opcode::from(instruction))
+        let mut operation = Opcode::from(instruction);
+        if !system.variant.supports(&operation) {
+            return Err(UnsupportedOpcodeError { code: instruction, pc: system.processor.registers.pc });
+        }
+        if operation.size() > 2 {
+            let next = system.memory.fetch(system.processor.registers.pc.wrapping_add(2))?;
+            operation = Opcode::from_long(instruction, next);
+        }
+
+        Processor::execute(system, operation)
     }
     fn execute(system: &mut Chip8, operation: Opcode) -> Result<(), ProcessorError> {
         system.processor.operation = operation;
-
-        println!("Stepping into opcode: {:?}", system.processor.operation);
+        trace!(target: "emul8::cpu", "{:04X}  {}", system.processor.registers.pc, operation);
 
         let mut dont_step = false;
 
         match system.processor.operation {
-            Opcode::_0nnn { n } => { panic!(); },
-            Opcode::_00E0 => { },
+            Opcode::_0nnn { n } => {
+                return Err(UnsupportedOpcodeError { code: n, pc: system.processor.registers.pc });
+            },
+            Opcode::_00E0 => {
+                system.display.clear();
+            },
             Opcode::_00EE => {
-                system.processor.registers.pop_stack();
+                system.processor.registers.pop_stack()?;
+            },
+            Opcode::_00Cn { n } => {
+                system.display.scroll_down(n as usize);
+            },
+            Opcode::_00FB => {
+                system.display.scroll_right(4);
+            },
+            Opcode::_00FC => {
+                system.display.scroll_left(4);
+            },
+            Opcode::_00FD => {
+                system.exit();
+            },
+            Opcode::_00FE => {
+                system.display.set_hires(false);
+            },
+            Opcode::_00FF => {
+                system.display.set_hires(true);
             },
             Opcode::_1nnn { n } => {
                 system.processor.registers.pc = n;
                 dont_step = true;
             },
             Opcode::_2nnn { n } => {
-                system.processor.registers.push_pc_stack();
+                system.processor.registers.push_pc_stack()?;
                 system.processor.registers.pc = n;
                 dont_step = true;
             },
             Opcode::_3xkk { x, k } => {
                 if system.processor.registers.read_v(x) == k {
-                    system.processor.registers.pc += 2;
+                    Processor::skip(system);
                 }
             },
             Opcode::_4xkk { x, k } => {
                 if system.processor.registers.read_v(x) != k {
-                    system.processor.registers.pc += 2;
+                    Processor::skip(system);
                 }
             },
             Opcode::_5xy0 { x, y } => {
                 if system.processor.registers.read_v(x) == system.processor.registers.read_v(y) {
-                    system.processor.registers.pc += 2;
+                    Processor::skip(system);
+                }
+            },
+            Opcode::_5xy2 { x, y } => {
+                let values: Vec<u8> = Processor::register_range(x, y).iter()
+                    .map(|r| system.processor.registers.read_v(*r)).collect();
+                system.memory.copy(system.processor.registers.i, &values)?;
+            },
+            Opcode::_5xy3 { x, y } => {
+                let range = Processor::register_range(x, y);
+                let values = system.memory.read_many(system.processor.registers.i, range.len())?;
+                for (r, val) in range.iter().zip(values.iter()) {
+                    system.processor.registers.write_v(*r, *val);
                 }
             },
             Opcode::_6xkk { x, k } => {
@@ -72,14 +116,23 @@ impl Processor {
             Opcode::_8xy1 { x, y } => {
                 let (vx, vy) = (system.processor.registers.read_v(x), system.processor.registers.read_v(y));
                 system.processor.registers.write_v(x, vx | vy);
+                if system.quirks.vf_reset {
+                    system.processor.registers.write_v(0xF, 0);
+                }
             },
             Opcode::_8xy2 { x, y } => {
                 let (vx, vy) = (system.processor.registers.read_v(x), system.processor.registers.read_v(y));
                 system.processor.registers.write_v(x, vx & vy);
+                if system.quirks.vf_reset {
+                    system.processor.registers.write_v(0xF, 0);
+                }
             },
             Opcode::_8xy3 { x, y } => {
                 let (vx, vy) = (system.processor.registers.read_v(x), system.processor.registers.read_v(y));
                 system.processor.registers.write_v(x, vx ^ vy);
+                if system.quirks.vf_reset {
+                    system.processor.registers.write_v(0xF, 0);
+                }
             },
             Opcode::_8xy4 { x, y } => {
                 let (vx, vy) = (system.processor.registers.read_v(x), system.processor.registers.read_v(y));
@@ -93,10 +146,10 @@ impl Processor {
                 system.processor.registers.write_v(x, val);
                 system.processor.registers.write_v(0xF, (!borrow) as u8);
             },
-            Opcode::_8xy6 { x, y: _ } => {
-                let vx = system.processor.registers.read_v(x);
-                system.processor.registers.write_v(0xF, vx & 0x1);
-                system.processor.registers.write_v(x, vx >> 1);
+            Opcode::_8xy6 { x, y } => {
+                let val = system.processor.registers.read_v(if system.quirks.shift_uses_vy { y } else { x });
+                system.processor.registers.write_v(x, val >> 1);
+                system.processor.registers.write_v(0xF, val & 0x1);
             },
             Opcode::_8xy7 { x, y } => {
                 let (vx, vy) = (system.processor.registers.read_v(x), system.processor.registers.read_v(y));
@@ -105,30 +158,74 @@ impl Processor {
                 system.processor.registers.write_v(0xF, (!borrow) as u8);
             },
             Opcode::_8xyE { x, y } => {
-                let vx = system.processor.registers.read_v(x);
-                system.processor.registers.write_v(0xF, vx >> 0x7);
-                system.processor.registers.write_v(x, vx << 1);
+                let val = system.processor.registers.read_v(if system.quirks.shift_uses_vy { y } else { x });
+                system.processor.registers.write_v(x, val << 1);
+                system.processor.registers.write_v(0xF, val >> 0x7);
             },
             Opcode::_9xy0 { x, y } => {
                 if system.processor.registers.read_v(x) != system.processor.registers.read_v(y) {
-                    system.processor.registers.pc += 2;
+                    Processor::skip(system);
                 }
             },
             Opcode::_Annn { n } => {
                 system.processor.registers.i = n;
             },
             Opcode::_Bnnn { n } => {
-                system.processor.registers.pc = n + system.processor.registers.read_v(0x0) as u16;
+                let x = if system.quirks.jump_uses_vx { (n >> 8) as u8 } else { 0x0 };
+                system.processor.registers.pc = n + system.processor.registers.read_v(x) as u16;
                 dont_step = true;
             },
-            Opcode::_Cxkk { x, k } => { },
-            Opcode::_Dxyn { x, y, n } => { },
-            Opcode::_Ex9E { x } => { },
-            Opcode::_ExA1 { x } => { },
+            Opcode::_Cxkk { x, k } => {
+                system.processor.registers.write_v(x, system.rng.next_byte() & k);
+            },
+            Opcode::_Dxyn { x, y, n } => {
+                if system.quirks.display_wait && !system.display.take_vblank() {
+                    // Stall until the start of the next frame.
+                    dont_step = true;
+                } else {
+                    let (vx, vy) = (system.processor.registers.read_v(x), system.processor.registers.read_v(y));
+                    let planes = system.display.plane_count();
+                    let collision = if n == 0 && system.variant != Variant::Chip8 {
+                        let sprite = system.memory.read_many(system.processor.registers.i, 32 * planes)?;
+                        system.display.draw_large_sprite(vx, vy, &sprite, system.quirks.clip_sprites)
+                    } else {
+                        let sprite = system.memory.read_many(system.processor.registers.i, n as usize * planes)?;
+                        system.display.draw_sprite(vx, vy, &sprite, system.quirks.clip_sprites)
+                    };
+                    system.processor.registers.write_v(0xF, collision as u8);
+                }
+            },
+            Opcode::_Ex9E { x } => {
+                if system.keypad.is_pressed(system.processor.registers.read_v(x) & 0xF) {
+                    Processor::skip(system);
+                }
+            },
+            Opcode::_ExA1 { x } => {
+                if !system.keypad.is_pressed(system.processor.registers.read_v(x) & 0xF) {
+                    Processor::skip(system);
+                }
+            },
+            Opcode::_F000 { n } => {
+                system.processor.registers.i = n;
+                // Step over the address word; the instruction word is stepped over below.
+                system.processor.registers.step_pc();
+            },
+            Opcode::_Fn01 { n } => {
+                system.display.set_planes(n);
+            },
+            Opcode::_F002 => {
+                let pattern = system.memory.read_many(system.processor.registers.i, PATTERN_SIZE)?;
+                system.audio.set_pattern(&pattern);
+            },
             Opcode::_Fx07 { x } => {
                 system.processor.registers.write_v(x, system.processor.registers.delay_timer);
             },
-            Opcode::_Fx0A { x } => { },
+            Opcode::_Fx0A { x } => {
+                match system.keypad.wait_for_key() {
+                    Some(key) => system.processor.registers.write_v(x, key),
+                    None => dont_step = true
+                }
+            },
             Opcode::_Fx15 { x } => {
                 system.processor.registers.delay_timer = system.processor.registers.read_v(x);
             },
@@ -136,39 +233,116 @@ impl Processor {
                 system.processor.registers.sound_timer = system.processor.registers.read_v(x);
             },
             Opcode::_Fx1E { x } => {
-                system.processor.registers.i += system.processor.registers.read_v(x) as u16;
+                let vx = system.processor.registers.read_v(x);
+                system.processor.registers.i = system.processor.registers.i.wrapping_add(vx as u16);
             },
             Opcode::_Fx29 { x } => {
-                system.processor.registers.i = 0x50 + (system.processor.registers.read_v(x) as u16) * 0x5;
+                system.processor.registers.i = FONT_ADDR + (system.processor.registers.read_v(x) as u16) * 0x5;
+            },
+            Opcode::_Fx30 { x } => {
+                system.processor.registers.i = LARGE_FONT_ADDR + (system.processor.registers.read_v(x) as u16 & 0xF) * 0xA;
+            },
+            Opcode::_Fx3A { x } => {
+                system.audio.set_pitch(system.processor.registers.read_v(x));
+            },
+            Opcode::_Fx33 { x } => {
+                let vx = system.processor.registers.read_v(x);
+                system.memory.copy(system.processor.registers.i, &[vx / 100, (vx / 10) % 10, vx % 10])?;
             },
-            Opcode::_Fx33 { x } => { },
             Opcode::_Fx55 { x } => {
-                system.memory.copy(system.processor.registers.i, &system.processor.registers.v);
+                let i = system.processor.registers.i;
+                system.memory.copy(i, &system.processor.registers.v[..=(x as usize)])?;
+                system.processor.registers.i = system.quirks.load_store_increment.apply(i, x);
             },
             Opcode::_Fx65 { x } => {
-                system.processor.registers.v = <[u8; 16]>::try_from(system.memory.read_many(system.processor.registers.i, 0xF))?;
+                let i = system.processor.registers.i;
+                let values = system.memory.read_many(i, x as usize + 1)?;
+                system.processor.registers.v[..=(x as usize)].copy_from_slice(&values);
+                system.processor.registers.i = system.quirks.load_store_increment.apply(i, x);
             },
-            Opcode::Invalid { code } => { return Err(ProcessorError::InvalidOpcodeError); }
+            Opcode::_Fx75 { x } => {
+                // A failure to persist the flags is not a fault of the program.
+                if let Err(err) = system.rpl.store(&system.processor.registers.v[..=(x as usize)]) {
+                    warn!(target: "emul8::cpu", "failed to persist the RPL user flags: {}", err);
+                }
+            },
+            Opcode::_Fx85 { x } => {
+                let len = x as usize + 1;
+                system.processor.registers.v[..len].copy_from_slice(&system.rpl.flags()[..len]);
+            },
+            Opcode::Invalid { code } => { return Err(InvalidOpcodeError { code, pc: system.processor.registers.pc }); }
         }
 
         if !dont_step {
-            system.processor.registers.pc += 2;
+            system.processor.registers.step_pc();
         }
 
         Ok(())
     }
+
+    /// Skips over the next instruction, which may be a four-byte XO-CHIP `LD I, long addr`.
+    fn skip(system: &mut Chip8) {
+        let next = system.processor.registers.pc.wrapping_add(2);
+        if system.variant == Variant::XoChip && system.memory.fetch(next) == Ok(0xF000) {
+            system.processor.registers.step_pc();
+        }
+        system.processor.registers.step_pc();
+    }
+
+    /// The registers V*x* through V*y*, in descending order if *x* > *y*.
+    fn register_range(x: u8, y: u8) -> Vec<u8> {
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+}
+impl Default for Processor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl Processor {
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
     pub fn cycle(system: &mut Chip8) -> Result<(), ProcessorError> {
-        println!("{:#X}", system.memory.read_16(system.processor.registers.pc)?);
+        Processor::execute_from(system, system.memory.fetch(system.processor.registers.pc)?)
+    }
+
+    /// Decodes the instruction at the program counter, without executing it.
+    pub fn peek_operation(system: &Chip8) -> Result<Opcode, MemoryError> {
+        let pc = system.processor.registers.pc;
+        let operation = Opcode::from(system.memory.fetch(pc)?);
+        match operation.size() {
+            2 => Ok(operation),
+            _ => Ok(Opcode::from_long(system.memory.fetch(pc)?, system.memory.fetch(pc.wrapping_add(2))?))
+        }
+    }
 
-        Processor::execute_from(system, system.memory.read_16(system.processor.registers.pc)?)
+    /// Describes the state of the machine for diagnosing a halt: the registers, the instructions
+    /// around the program counter, and memory.
+    pub fn halt_dump(system: &Chip8) -> String {
+        format!("{}\n{}\n{}", system.processor.registers.dump(), Processor::disassemble_around_pc(system),
+            system.memory.dump())
     }
 
-    pub fn halt(system: &Chip8) {
-        println!("CPU halted!\n");
-        println!("{}", system.processor.registers.dump());
-        println!("{}", system.memory.dump());
+    /// Lists the instructions surrounding the program counter.
+    fn disassemble_around_pc(system: &Chip8) -> String {
+        let pc = system.processor.registers.pc;
+        let from = pc.saturating_sub(8);
+        // In `usize`, as the end of a 64 KiB memory is past the last address.
+        let len = (pc as usize + 10).min(system.memory.size()).saturating_sub(from as usize);
+
+        match system.memory.read_many(from, len) {
+            Ok(data) => Disassembler::new(system.variant).disassemble_linear(&data, from).listing(Some(pc)),
+            Err(err) => format!("{}\n", err)
+        }
     }
 }
 
@@ -210,65 +384,453 @@ impl Registers {
         self.v[i as usize] = val;
     }
 
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+    /// Counts both timers down by one, stopping at zero.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+    /// The stack, holding the return addresses of the subroutines called at 1 up to `sp()`.
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.stack
+    }
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+    /// Advances the program counter past the current instruction.
+    pub fn step_pc(&mut self) {
+        self.pc = self.pc.wrapping_add(2);
+    }
+
     pub fn peek_stack(&self) -> u16 {
         self.stack[self.sp as usize]
     }
-    pub fn pop_stack(&mut self) -> u16 {
+    pub fn pop_stack(&mut self) -> Result<u16, ProcessorError> {
+        if self.sp == 0 {
+            return Err(StackUnderflowError { pc: self.pc });
+        }
+
         self.pc = self.peek_stack();
         self.sp -= 1;
-        self.pc
+        Ok(self.pc)
     }
-    pub fn push_pc_stack(&mut self) {
-        self.push_stack(self.pc);
+    pub fn push_pc_stack(&mut self) -> Result<(), ProcessorError> {
+        self.push_stack(self.pc)
     }
-    pub fn push_stack(&mut self, addr: u16) {
+    pub fn push_stack(&mut self, addr: u16) -> Result<(), ProcessorError> {
+        if self.sp as usize == self.stack.len() - 1 {
+            return Err(StackOverflowError { pc: self.pc });
+        }
+
         self.sp += 1;
         self.stack[self.sp as usize] = addr;
+        Ok(())
     }
 
     pub fn dump(&self) -> String {
         let mut writer = String::new();
 
         // Program Counter
-        writeln!(writer, "{:<4}{:#X}", "PC", self.pc);
+        writeln!(writer, "{:<4}{:#X}", "PC", self.pc).unwrap();
 
         // Stack info
-        writeln!(writer, "{:<4}{:#X}", "SP",  self.sp);
-        writeln!(writer, "stack:");
+        writeln!(writer, "{:<4}{:#X}", "SP",  self.sp).unwrap();
+        writeln!(writer, "stack:").unwrap();
         for (i, addr) in self.stack.iter().rev().enumerate() {
             writeln!(writer, "  {} {:#06X}",
-                     if self.sp == (0xF - i as u8) { ">" } else { " " }, addr);
+                     if self.sp == (0xF - i as u8) { ">" } else { " " }, addr).unwrap();
         }
-        writeln!(writer, "");
+        writeln!(writer).unwrap();
 
         // Registers
-        writeln!(writer, "registers:");
+        writeln!(writer, "registers:").unwrap();
         for (i, row) in self.v.chunks(2).enumerate() {
             writeln!(writer, "{:<4}{:<#8X}{:<4}{:#X}",
                      format!("V{:X}", i*2), row[0],
-                     format!("V{:X}", i*2+1), row[1]);
+                     format!("V{:X}", i*2+1), row[1]).unwrap();
         }
-        writeln!(writer, "{:<4}{:#X}", "I",  self.i);
-        writeln!(writer, "{:<4}{:#X}", "DT", self.delay_timer);
-        writeln!(writer, "{:<4}{:#X}", "ST", self.sound_timer);
+        writeln!(writer, "{:<4}{:#X}", "I",  self.i).unwrap();
+        writeln!(writer, "{:<4}{:#X}", "DT", self.delay_timer).unwrap();
+        writeln!(writer, "{:<4}{:#X}", "ST", self.sound_timer).unwrap();
 
         writer
     }
+
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.u16(self.pc);
+        out.u8(self.sp);
+        for addr in &self.stack {
+            out.u16(*addr);
+        }
+        out.bytes(&self.v);
+        out.u16(self.i);
+        out.u8(self.delay_timer);
+        out.u8(self.sound_timer);
+    }
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, SnapshotError> {
+        let mut registers = Self::new();
+        registers.pc = input.u16()?;
+        registers.sp = input.u8()?;
+        if registers.sp as usize >= registers.stack.len() {
+            return Err(input.invalid());
+        }
+        for addr in registers.stack.iter_mut() {
+            *addr = input.u16()?;
+        }
+        registers.v = input.array()?;
+        registers.i = input.u16()?;
+        registers.delay_timer = input.u8()?;
+        registers.sound_timer = input.u8()?;
+        Ok(registers)
+    }
+}
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProcessorError {
-    InvalidOpcodeError
+    /// The word *code* fetched from *pc* does not decode to any instruction.
+    InvalidOpcodeError { code: u16, pc: u16 },
+    /// The instruction at *pc* is valid, but not part of the active instruction set.
+    UnsupportedOpcodeError { code: u16, pc: u16 },
+    /// The instruction at *pc* accessed memory out of bounds.
+    MemoryError(MemoryError),
+    /// A subroutine call at *pc* exceeded the depth of the stack.
+    StackOverflowError { pc: u16 },
+    /// A return at *pc* was executed with an empty stack.
+    StackUnderflowError { pc: u16 },
+    /// A ROM of *size* bytes does not fit into the *capacity* bytes of program memory.
+    RomTooLargeError { size: usize, capacity: usize }
+}
+impl fmt::Display for ProcessorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidOpcodeError { code, pc } => write!(f, "invalid opcode {:#06X} at {:#05X}", code, pc),
+            UnsupportedOpcodeError { code, pc } => write!(f, "unsupported opcode {:#06X} at {:#05X}", code, pc),
+            MemoryError(err) => write!(f, "{}", err),
+            StackOverflowError { pc } => write!(f, "stack overflow at {:#05X}", pc),
+            StackUnderflowError { pc } => write!(f, "stack underflow at {:#05X}", pc),
+            RomTooLargeError { size, capacity } => write!(f, "ROM of {} bytes exceeds the {} bytes of program memory", size, capacity)
+        }
+    }
+}
+impl Error for ProcessorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MemoryError(err) => Some(err),
+            _ => None
+        }
+    }
 }
-// TODO: Better error handling
 impl From<MemoryError> for ProcessorError {
     fn from(err: MemoryError) -> Self {
-        match err {
-            MemoryAccessError => InvalidOpcodeError
-        }
+        MemoryError(err)
     }
 }
-impl From<TryFromSliceError> for ProcessorError {
-    fn from(_: TryFromSliceError) -> Self {
-        InvalidOpcodeError
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{IndexIncrement, Quirks, RunState};
+
+    fn system(program: &[u8], quirks: Quirks) -> Chip8 {
+        let mut system = Chip8::with_quirks(quirks);
+        system.init().unwrap();
+        system.bios.load_rom(&mut system.memory, program).unwrap();
+        system
+    }
+
+    #[test]
+    fn fx33_stores_bcd_at_i() {
+        let mut system = system(&[0xF3, 0x33], Quirks::default());
+        system.processor.registers.v[0x3] = 254;
+        system.processor.registers.i = 0x300;
+
+        Processor::cycle(&mut system).unwrap();
+
+        assert_eq!(&*system.memory.read_many(0x300, 3).unwrap(), &[2, 5, 4]);
+        assert_eq!(system.processor.registers.i, 0x300);
+        assert_eq!(system.processor.registers.pc, 0x202);
+    }
+
+    #[test]
+    fn fx33_out_of_bounds_faults_without_writing() {
+        let mut system = system(&[0xF0, 0x33], Quirks::default());
+        system.processor.registers.i = 0xFFE;
+
+        assert_eq!(Processor::cycle(&mut system),
+                   Err(MemoryError(MemoryError::MemoryAccessError { addr: 0xFFE, len: 3 })));
+        assert_eq!(system.memory.read(0xFFE), Ok(0));
+        assert_eq!(system.processor.registers.pc, 0x200);
+    }
+
+    #[test]
+    fn fx55_stores_v0_through_vx_inclusive() {
+        let mut system = system(&[0xF2, 0x55], Quirks::default());
+        system.processor.registers.v = [0xA; 16];
+        system.processor.registers.v[..3].copy_from_slice(&[1, 2, 3]);
+        system.processor.registers.i = 0x300;
+
+        Processor::cycle(&mut system).unwrap();
+
+        assert_eq!(&*system.memory.read_many(0x300, 4).unwrap(), &[1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn fx55_increments_i_per_quirk() {
+        for (increment, expected) in &[(IndexIncrement::Unchanged, 0x300), (IndexIncrement::X, 0x302),
+                                       (IndexIncrement::XPlusOne, 0x303)] {
+            let mut system = system(&[0xF2, 0x55], Quirks { load_store_increment: *increment, ..Quirks::default() });
+            system.processor.registers.i = 0x300;
+
+            Processor::cycle(&mut system).unwrap();
+
+            assert_eq!(system.processor.registers.i, *expected);
+        }
+    }
+
+    #[test]
+    fn fx55_out_of_bounds_faults() {
+        let mut system = system(&[0xFF, 0x55], Quirks::default());
+        system.processor.registers.i = 0xFF8;
+
+        assert_eq!(Processor::cycle(&mut system),
+                   Err(MemoryError(MemoryError::MemoryAccessError { addr: 0xFF8, len: 16 })));
+        assert_eq!(system.processor.registers.i, 0xFF8);
+    }
+
+    #[test]
+    fn fx65_reads_v0_through_vx_inclusive() {
+        let mut system = system(&[0xF2, 0x65], Quirks::default());
+        system.memory.copy(0x300, &[1, 2, 3, 4]).unwrap();
+        system.processor.registers.v[0x3] = 0xA;
+        system.processor.registers.i = 0x300;
+
+        Processor::cycle(&mut system).unwrap();
+
+        assert_eq!(&system.processor.registers.v[..4], &[1, 2, 3, 0xA]);
+    }
+
+    #[test]
+    fn fx65_reads_all_registers() {
+        let mut system = system(&[0xFF, 0x65], Quirks::default());
+        let values: Vec<u8> = (1..=16).collect();
+        system.memory.copy(0x300, &values).unwrap();
+        system.processor.registers.i = 0x300;
+
+        Processor::cycle(&mut system).unwrap();
+
+        assert_eq!(&system.processor.registers.v[..], &values[..]);
+    }
+
+    #[test]
+    fn fx65_increments_i_per_quirk() {
+        for (increment, expected) in &[(IndexIncrement::Unchanged, 0x300), (IndexIncrement::X, 0x302),
+                                       (IndexIncrement::XPlusOne, 0x303)] {
+            let mut system = system(&[0xF2, 0x65], Quirks { load_store_increment: *increment, ..Quirks::default() });
+            system.processor.registers.i = 0x300;
+
+            Processor::cycle(&mut system).unwrap();
+
+            assert_eq!(system.processor.registers.i, *expected);
+        }
+    }
+
+    #[test]
+    fn fx65_out_of_bounds_faults_without_reading() {
+        let mut system = system(&[0xF3, 0x65], Quirks::default());
+        system.processor.registers.v[0x0] = 0xA;
+        system.processor.registers.i = 0xFFE;
+
+        assert_eq!(Processor::cycle(&mut system),
+                   Err(MemoryError(MemoryError::MemoryAccessError { addr: 0xFFE, len: 4 })));
+        assert_eq!(system.processor.registers.v[0x0], 0xA);
+    }
+
+    #[test]
+    fn dxyn_sets_vf_on_collision() {
+        let mut system = system(&[0xD0, 0x11, 0xD0, 0x11], Quirks { display_wait: false, ..Quirks::default() });
+        system.memory.write(0x300, 0xC0).unwrap();
+        system.processor.registers.v[0xF] = 0xA;
+        system.processor.registers.i = 0x300;
+
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(system.processor.registers.v[0xF], 0);
+        assert!(system.display.pixel(0, 0) && system.display.pixel(1, 0));
+
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(system.processor.registers.v[0xF], 1);
+        assert!(system.display.frame().iter().all(|pixel| *pixel == 0));
+    }
+
+    #[test]
+    fn cls_clears_the_screen() {
+        let mut system = system(&[0x00, 0xE0], Quirks::default());
+        system.display.draw_sprite(10, 10, &[0xFF, 0xFF], false);
+
+        Processor::cycle(&mut system).unwrap();
+
+        assert!(system.display.frame().iter().all(|pixel| *pixel == 0));
+        assert_eq!(system.processor.registers.pc, 0x202);
+    }
+
+    /// A machine of `variant` with its usual quirks, running `program`.
+    fn variant_system(variant: Variant, program: &[u8]) -> Chip8 {
+        let mut system = system(program, variant.quirks());
+        system.variant = variant;
+        system
+    }
+
+    #[test]
+    fn schip_scrolls_the_screen() {
+        let mut system = variant_system(Variant::SuperChip, &[0x00, 0xFF, 0x00, 0xC3, 0x00, 0xFB, 0x00, 0xFC]);
+        Processor::cycle(&mut system).unwrap();
+        system.display.draw_sprite(5, 0, &[0x80], false);
+
+        Processor::cycle(&mut system).unwrap();
+        assert!(system.display.pixel(5, 3) && !system.display.pixel(5, 0));
+        Processor::cycle(&mut system).unwrap();
+        assert!(system.display.pixel(9, 3) && !system.display.pixel(5, 3));
+        Processor::cycle(&mut system).unwrap();
+        assert!(system.display.pixel(5, 3) && !system.display.pixel(9, 3));
+        assert_eq!(system.processor.registers.pc, 0x208);
+    }
+
+    #[test]
+    fn schip_switches_resolution_and_exits() {
+        let mut system = variant_system(Variant::SuperChip, &[0x00, 0xFF, 0x00, 0xFE, 0x00, 0xFD]);
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!((system.display.width(), system.display.height()), (128, 64));
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!((system.display.width(), system.display.height()), (64, 32));
+
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(system.state(), RunState::Exited);
+    }
+
+    #[test]
+    fn schip_dxy0_draws_16x16_sprites() {
+        let mut system = variant_system(Variant::SuperChip, &[0x00, 0xFF, 0xD0, 0x10, 0xD0, 0x10]);
+        system.memory.copy(0x300, &[0xFF; 32]).unwrap();
+        system.processor.registers.i = 0x300;
+
+        Processor::cycle(&mut system).unwrap();
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(system.processor.registers.v[0xF], 0);
+        assert!(system.display.pixel(0, 0) && system.display.pixel(15, 15));
+        assert!(!system.display.pixel(16, 0) && !system.display.pixel(0, 16));
+
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(system.processor.registers.v[0xF], 1);
+        assert!(!system.display.pixel(15, 15));
+    }
+
+    #[test]
+    fn schip_fx75_and_fx85_store_and_restore_the_flags() {
+        let mut system = variant_system(Variant::SuperChip, &[0xF2, 0x75, 0xF3, 0x85]);
+        system.processor.registers.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(&system.rpl.flags()[..4], &[1, 2, 3, 0]);
+
+        system.processor.registers.v[..4].copy_from_slice(&[9; 4]);
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(&system.processor.registers.v[..5], &[1, 2, 3, 0, 0]);
+    }
+
+    #[test]
+    fn xochip_f000_loads_a_long_address() {
+        let mut system = variant_system(Variant::XoChip, &[0xF0, 0x00, 0x12, 0x34, 0x30, 0x00, 0xF0, 0x00, 0x00, 0x00]);
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!((system.processor.registers.i, system.processor.registers.pc), (0x1234, 0x204));
+
+        // Skips step over the whole of it.
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(system.processor.registers.pc, 0x20A);
+    }
+
+    #[test]
+    fn xochip_5xy2_and_5xy3_save_and_load_ranges_in_either_order() {
+        let mut system = variant_system(Variant::XoChip, &[0x51, 0x32, 0x53, 0x12, 0x54, 0x63]);
+        system.processor.registers.v[1..4].copy_from_slice(&[1, 2, 3]);
+        system.processor.registers.i = 0x300;
+
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(&*system.memory.read_many(0x300, 3).unwrap(), &[1, 2, 3]);
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(&*system.memory.read_many(0x300, 3).unwrap(), &[3, 2, 1]);
+
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(&system.processor.registers.v[4..7], &[3, 2, 1]);
+        assert_eq!(system.processor.registers.i, 0x300);
+    }
+
+    #[test]
+    fn xochip_fn01_selects_the_planes_drawn_to() {
+        let mut system = variant_system(Variant::XoChip, &[0xF3, 0x01, 0xD0, 0x01, 0xF2, 0x01, 0x00, 0xE0]);
+        system.memory.copy(0x300, &[0x80, 0xC0]).unwrap();
+        system.processor.registers.i = 0x300;
+
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!(system.display.planes(), 0x3);
+        // A row for each plane.
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!((system.display.color(0, 0), system.display.color(1, 0)), (3, 2));
+
+        Processor::cycle(&mut system).unwrap();
+        Processor::cycle(&mut system).unwrap();
+        assert_eq!((system.display.color(0, 0), system.display.color(1, 0)), (1, 0));
+    }
+
+    #[test]
+    fn xochip_f002_loads_the_audio_pattern() {
+        let mut system = variant_system(Variant::XoChip, &[0xF0, 0x02]);
+        let pattern: Vec<u8> = (0..PATTERN_SIZE as u8).collect();
+        system.memory.copy(0x300, &pattern).unwrap();
+        system.processor.registers.i = 0x300;
+
+        Processor::cycle(&mut system).unwrap();
+
+        assert_eq!(&system.audio.pattern()[..], &pattern[..]);
+    }
+
+    #[test]
+    fn halt_dump_lists_the_end_of_a_full_address_space() {
+        let mut system = Chip8::with_variant(Variant::XoChip, Variant::XoChip.quirks());
+        system.memory.copy(0xFFFC, &[0x00, 0xE0, 0x00, 0xEE]).unwrap();
+        system.processor.registers.pc = 0xFFFE;
+
+        let dump = Processor::halt_dump(&system);
+        assert!(dump.contains("  FFFC:  00E0       CLS\n"), "{}", dump);
+        assert!(dump.contains("> FFFE:  00EE       RET\n"), "{}", dump);
+    }
+
+    #[test]
+    fn fault_leaves_the_halt_dump_to_the_host() {
+        let mut system = system(&[0x00, 0xE0, 0x00, 0x00], Quirks::default());
+        system.cycle().unwrap();
+        assert!(system.cycle().is_err());
+
+        let dump = Processor::halt_dump(&system);
+        assert!(dump.starts_with("PC  0x202\n"));
+        assert!(dump.contains("  0200:  00E0       CLS\n"));
+        assert!(dump.contains("\n0200:  00 E0 00 00 "));
     }
 }
\ No newline at end of file
@@ -0,0 +1,162 @@
+use crate::chip8::snapshot::{SectionReader, SectionWriter, SnapshotError};
+use std::fmt;
+
+/// How `LD [I], Vx` and `LD Vx, [I]` leave the I register after the transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left unchanged.
+    Unchanged,
+    /// I is incremented by *x*.
+    X,
+    /// I is incremented by *x* + 1, pointing just past the last register transferred.
+    XPlusOne
+}
+impl IndexIncrement {
+    /// The value of I after transferring registers V0 through V*x* starting at `i`.
+    pub fn apply(&self, i: u16, x: u8) -> u16 {
+        match self {
+            IndexIncrement::Unchanged => i,
+            IndexIncrement::X => i.wrapping_add(x as u16),
+            IndexIncrement::XPlusOne => i.wrapping_add(x as u16 + 1)
+        }
+    }
+}
+
+/// Behaviours of the instructions that were interpreted differently over the history of CHIP-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `SHR`/`SHL` (8*xy*6/8*xy*E) shift V*y* into V*x*, instead of shifting V*x* in place.
+    pub shift_uses_vy: bool,
+    /// `LD [I], Vx`/`LD Vx, [I]` (F*x*55/F*x*65) move I past the registers transferred.
+    pub load_store_increment: IndexIncrement,
+    /// `JP V0, addr` (B*nnn*) jumps to *xnn* + V*x*, instead of *nnn* + V0.
+    pub jump_uses_vx: bool,
+    /// `OR`/`AND`/`XOR` (8*xy*1/8*xy*2/8*xy*3) reset VF to 0.
+    pub vf_reset: bool,
+    /// Sprites are clipped at the edges of the screen, instead of wrapping around to the other side.
+    pub clip_sprites: bool,
+    /// `DRW` (D*xyn*) waits for the next 60 Hz vertical blank, so at most one sprite is drawn per frame.
+    pub display_wait: bool
+}
+impl Quirks {
+    /// The original interpreter of the RCA COSMAC VIP.
+    pub fn cosmac_vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increment: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub fn chip48() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increment: IndexIncrement::X,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP-48 calculators.
+    pub fn super_chip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increment: IndexIncrement::Unchanged,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false
+        }
+    }
+
+    /// XO-CHIP, as implemented by Octo.
+    pub fn xo_chip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increment: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false
+        }
+    }
+
+    /// Applies a comma-separated list of quirk presets and individual quirks in order. An
+    /// individual quirk is enabled by its name and disabled by its name prefixed with `no-`, e.g.
+    /// `vip,no-display-wait`.
+    pub fn apply_list(mut self, list: &str) -> Result<Self, String> {
+        for item in list.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (name, enabled) = match item.strip_prefix("no-") {
+                Some(name) => (name, false),
+                None => (item, true)
+            };
+
+            match name {
+                "vip" | "chip8" if enabled => self = Quirks::cosmac_vip(),
+                "chip48" if enabled => self = Quirks::chip48(),
+                "schip" | "superchip" if enabled => self = Quirks::super_chip(),
+                "xochip" if enabled => self = Quirks::xo_chip(),
+                "shift-vy" => self.shift_uses_vy = enabled,
+                "jump-vx" => self.jump_uses_vx = enabled,
+                "vf-reset" => self.vf_reset = enabled,
+                "clip" => self.clip_sprites = enabled,
+                "display-wait" => self.display_wait = enabled,
+                "load-store" => self.load_store_increment = if enabled { IndexIncrement::XPlusOne } else { IndexIncrement::Unchanged },
+                "load-store-x" => self.load_store_increment = if enabled { IndexIncrement::X } else { IndexIncrement::Unchanged },
+                _ => return Err(format!("unknown quirk `{}`", item))
+            }
+        }
+        Ok(self)
+    }
+
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        let flags = [self.shift_uses_vy, self.jump_uses_vx, self.vf_reset, self.clip_sprites, self.display_wait];
+        out.u8(flags.iter().rev().fold(0, |mask, flag| (mask << 1) | *flag as u8));
+        out.u8(match self.load_store_increment {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::X => 1,
+            IndexIncrement::XPlusOne => 2
+        });
+    }
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, SnapshotError> {
+        let flags = input.u8()?;
+        let load_store_increment = match input.u8()? {
+            0 => IndexIncrement::Unchanged,
+            1 => IndexIncrement::X,
+            2 => IndexIncrement::XPlusOne,
+            _ => return Err(input.invalid())
+        };
+        Ok(Self {
+            shift_uses_vy: flags & 0x01 != 0,
+            load_store_increment,
+            jump_uses_vx: flags & 0x02 != 0,
+            vf_reset: flags & 0x04 != 0,
+            clip_sprites: flags & 0x08 != 0,
+            display_wait: flags & 0x10 != 0
+        })
+    }
+}
+/// Lists every quirk, in the form read back by `Quirks::apply_list`.
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |enabled: bool| if enabled { "" } else { "no-" };
+        let load_store = match self.load_store_increment {
+            IndexIncrement::Unchanged => "no-load-store",
+            IndexIncrement::X => "load-store-x",
+            IndexIncrement::XPlusOne => "load-store"
+        };
+        write!(f, "{}shift-vy,{},{}jump-vx,{}vf-reset,{}clip,{}display-wait", flag(self.shift_uses_vy), load_store,
+            flag(self.jump_uses_vx), flag(self.vf_reset), flag(self.clip_sprites), flag(self.display_wait))
+    }
+}
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::cosmac_vip()
+    }
+}
\ No newline at end of file
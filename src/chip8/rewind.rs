@@ -0,0 +1,416 @@
+use crate::chip8::{Chip8, Keypad};
+use crate::chip8::snapshot::SnapshotError;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+use self::RewindError::*;
+
+/// Default number of frames between snapshots, one a second.
+pub const DEFAULT_INTERVAL: u64 = 60;
+/// Default number of snapshots kept, ten minutes' worth at the default interval.
+pub const DEFAULT_CAPACITY: usize = 600;
+
+/// A snapshot taken at the start of *frame*, after *cycles* processor cycles.
+struct Entry {
+    frame: u64,
+    cycles: u64,
+    /// The save state of the newest entry; for every other entry, its save state as a delta from
+    /// that of the entry after it.
+    data: Vec<u8>
+}
+
+/// A bounded history of the machine, to step execution backwards by instructions or frames.
+///
+/// Snapshots are taken every `interval` frames, keeping up to `capacity` of them, the oldest
+/// being dropped first. Only the newest is stored whole, every other as the difference from the
+/// one after it. Any state in between is recreated by running on from the snapshot before it,
+/// with the keypad input given at the time replayed.
+pub struct Rewind {
+    interval: u64,
+    capacity: usize,
+    entries: VecDeque<Entry>,
+    /// The keypad as the host left it, each time it changed, by the cycle it was recorded at.
+    inputs: BTreeMap<u64, Keypad>
+}
+impl Rewind {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            entries: VecDeque::new(),
+            inputs: BTreeMap::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Number of bytes taken up by the snapshots.
+    pub fn size(&self) -> usize {
+        self.entries.iter().map(|entry| entry.data.len()).sum()
+    }
+    /// The cycle of the oldest state that can be rewound to.
+    pub fn oldest(&self) -> Option<u64> {
+        self.entries.front().map(|entry| entry.cycles)
+    }
+
+    /// Records the machine, taking a snapshot if one is due.
+    ///
+    /// Must be called before running the machine on, at least once every frame and whenever the
+    /// host has changed the keypad. After rewinding, the history past the point rewound to is
+    /// dropped as soon as the machine is recorded again.
+    pub fn record(&mut self, system: &Chip8) {
+        let (frame, cycles) = (system.timers.frames(), system.timers.cycles());
+        self.truncate(cycles);
+
+        let input = self.inputs.values().next_back();
+        if input != Some(&system.keypad) {
+            self.inputs.insert(cycles, system.keypad.clone());
+        }
+
+        let due = self.entries.back().map_or(true, |entry| frame >= entry.frame + self.interval);
+        if due {
+            let state = system.save_state();
+            if let Some(newest) = self.entries.back_mut() {
+                newest.data = delta(&state, &newest.data);
+            }
+            self.entries.push_back(Entry { frame, cycles, data: state });
+
+            if self.entries.len() > self.capacity {
+                self.entries.pop_front();
+                if let Some(oldest) = self.oldest() {
+                    // The last input before the oldest snapshot is part of it.
+                    let last = self.inputs.range(..=oldest).next_back().map(|(cycles, _)| *cycles);
+                    self.inputs = self.inputs.split_off(&last.unwrap_or(oldest));
+                }
+            }
+        }
+    }
+
+    /// Drops the history past `cycles`.
+    fn truncate(&mut self, cycles: u64) {
+        self.inputs.split_off(&(cycles + 1));
+
+        let keep = self.entries.iter().take_while(|entry| entry.cycles <= cycles).count();
+        if keep < self.entries.len() {
+            let state = if keep > 0 { Some(self.state(keep - 1)) } else { None };
+            self.entries.truncate(keep);
+            if let (Some(newest), Some(state)) = (self.entries.back_mut(), state) {
+                newest.data = state;
+            }
+        }
+    }
+
+    /// Recreates the save state of the entry at `index`.
+    fn state(&self, index: usize) -> Vec<u8> {
+        let newest = self.entries.len() - 1;
+        let mut state = self.entries[newest].data.clone();
+        for entry in self.entries.range(index..newest).rev() {
+            state = apply(&state, &entry.data);
+        }
+        state
+    }
+
+    /// Rewinds the machine by one processor cycle.
+    pub fn step_back(&mut self, system: &mut Chip8) -> Result<(), RewindError> {
+        let cycles = system.timers.cycles().checked_sub(1).ok_or(OutOfHistoryError)?;
+        self.seek(system, cycles)
+    }
+    /// Rewinds the machine to the start of the current frame, or to the start of the frame before
+    /// if it is already at the start of one.
+    pub fn step_back_frame(&mut self, system: &mut Chip8) -> Result<(), RewindError> {
+        let (frame, cycles) = (system.timers.frames(), system.timers.cycles());
+        self.seek_frame(system, frame, cycles)?;
+        if system.timers.cycles() >= cycles {
+            self.seek_frame(system, frame.checked_sub(1).ok_or(OutOfHistoryError)?, cycles)?;
+        }
+        Ok(())
+    }
+    /// Rewinds the machine to the start of `frame`, going back no further than `cycles`.
+    fn seek_frame(&mut self, system: &mut Chip8, frame: u64, cycles: u64) -> Result<(), RewindError> {
+        let index = self.entries.iter().rposition(|entry| entry.frame <= frame && entry.cycles <= cycles)
+            .ok_or(OutOfHistoryError)?;
+        self.replay(system, index, |system| system.timers.frames() >= frame)
+    }
+    /// Rewinds the machine to the state it was in after `cycles` processor cycles.
+    pub fn seek(&mut self, system: &mut Chip8, cycles: u64) -> Result<(), RewindError> {
+        let index = self.entries.iter().rposition(|entry| entry.cycles <= cycles).ok_or(OutOfHistoryError)?;
+        self.replay(system, index, |system| system.timers.cycles() >= cycles)
+    }
+
+    /// Restores the snapshot at `index` into `system` and runs it until `done`, replaying the
+    /// recorded input.
+    ///
+    /// The replayed cycles already ran once, so they are not traced, screenshot or recorded again.
+    fn replay<F: Fn(&Chip8) -> bool>(&self, system: &mut Chip8, index: usize, done: F) -> Result<(), RewindError> {
+        system.load_state(&self.state(index))?;
+
+        let (tracer, camera, recorder) = (system.tracer.take(), system.camera.take(), system.recorder.take());
+        while !done(system) && system.is_running() {
+            if let Some(keypad) = self.inputs.get(&system.timers.cycles()) {
+                system.keypad = keypad.clone();
+            }
+            // A fault ends the replay in the same state as it ended the original run.
+            if system.cycle().is_err() {
+                break;
+            }
+        }
+        system.tracer = tracer;
+        system.camera = camera;
+        system.recorder = recorder;
+        Ok(())
+    }
+}
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVAL, DEFAULT_CAPACITY)
+    }
+}
+
+/// Encodes `target` as its differences from `base`: its length, followed by runs of bytes equal
+/// to the base alternating with runs of bytes that differ, the latter XORed with the base.
+/// Lengths are LEB128 encoded.
+fn delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let diff = |i: usize| target[i] ^ base.get(i).copied().unwrap_or(0);
+
+    let mut out = Vec::new();
+    write_length(&mut out, target.len());
+    let mut i = 0;
+    while i < target.len() {
+        let start = i;
+        while i < target.len() && diff(i) == 0 {
+            i += 1;
+        }
+        write_length(&mut out, i - start);
+
+        let start = i;
+        while i < target.len() && diff(i) != 0 {
+            i += 1;
+        }
+        write_length(&mut out, i - start);
+        out.extend((start..i).map(diff));
+    }
+    out
+}
+
+/// Recreates the target of a `delta` from its `base`.
+fn apply(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut input = delta.iter().copied();
+    let len = read_length(&mut input);
+    let mut target: Vec<u8> = base.iter().copied().chain(std::iter::repeat(0)).take(len).collect();
+
+    let mut i = 0;
+    while i < len {
+        i += read_length(&mut input);
+        for _ in 0..read_length(&mut input) {
+            target[i] ^= input.next().unwrap_or(0);
+            i += 1;
+        }
+    }
+    target
+}
+
+fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 0x80 {
+        out.push((len & 0x7F) as u8 | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+}
+fn read_length<I: Iterator<Item = u8>>(input: &mut I) -> usize {
+    let mut len = 0;
+    for (shift, byte) in input.enumerate() {
+        len |= ((byte & 0x7F) as usize) << (7 * shift);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    len
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewindError {
+    /// The state asked for is older than the oldest snapshot kept.
+    OutOfHistoryError,
+    /// A snapshot could not be restored.
+    SnapshotError(SnapshotError)
+}
+impl fmt::Display for RewindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutOfHistoryError => write!(f, "no history left to rewind to"),
+            SnapshotError(err) => write!(f, "{}", err)
+        }
+    }
+}
+impl Error for RewindError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError(err) => Some(err),
+            _ => None
+        }
+    }
+}
+impl From<SnapshotError> for RewindError {
+    fn from(err: SnapshotError) -> Self {
+        SnapshotError(err)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{boot, Camera, Recorder, Screenshot, Tracer, Variant};
+    use std::io;
+
+    /// A machine running a program whose state depends on the random numbers and the keypad.
+    fn system() -> Chip8 {
+        let mut system = boot(Variant::Chip8, "
+            : main
+                v1 := 5
+                loop
+                    v0 := random 0xFF
+                    if v1 key then v2 += 1
+                    i := 0x300
+                    save v2
+                again
+        ");
+        system.seed(7);
+        system
+    }
+
+    /// Runs `frames` frames, pressing key 5 for a while, recording every frame; returns the
+    /// state after every cycle.
+    fn run(system: &mut Chip8, rewind: &mut Rewind, frames: u64) -> Vec<Vec<u8>> {
+        let mut states = vec![system.save_state()];
+        for frame in 0..frames {
+            match frame {
+                3 => system.keypad.press(5),
+                9 => system.keypad.release(5),
+                _ => { }
+            }
+            rewind.record(system);
+            while system.timers.frames() == frame {
+                system.cycle().unwrap();
+                states.push(system.save_state());
+            }
+        }
+        states
+    }
+
+    #[test]
+    fn deltas_recreate_their_target() {
+        let base: Vec<u8> = (0..300).map(|i| (i * 7) as u8).collect();
+        let mut changed = base.clone();
+        changed[0] ^= 1;
+        changed[150..290].iter_mut().for_each(|byte| *byte = 0xAA);
+
+        for target in [base.clone(), changed, base[..100].to_vec(), [&base[..], &[1, 2, 3]].concat(), Vec::new()] {
+            assert_eq!(apply(&base, &delta(&base, &target)), target);
+        }
+        assert_eq!(delta(&base, &base), vec![0xAC, 0x02, 0xAC, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn steps_back_through_every_cycle() {
+        let (mut system, mut rewind) = (system(), Rewind::new(4, 100));
+        let states = run(&mut system, &mut rewind, 12);
+        assert_eq!(rewind.len(), 3);
+
+        for cycles in (0..(states.len() - 1)).rev() {
+            rewind.step_back(&mut system).unwrap();
+            assert_eq!(system.timers.cycles(), cycles as u64);
+            assert_eq!(system.save_state(), states[cycles], "state after {} cycles", cycles);
+        }
+        assert_eq!(rewind.step_back(&mut system), Err(OutOfHistoryError));
+    }
+
+    #[test]
+    fn steps_back_to_the_start_of_frames() {
+        let (mut system, mut rewind) = (system(), Rewind::new(4, 100));
+        run(&mut system, &mut rewind, 12);
+        for _ in 0..5 {
+            system.cycle().unwrap();
+        }
+
+        let per_frame = system.timers.clock_speed() as u64 / 60;
+        rewind.step_back_frame(&mut system).unwrap();
+        assert_eq!((system.timers.frames(), system.timers.cycles()), (12, 12 * per_frame));
+        rewind.step_back_frame(&mut system).unwrap();
+        assert_eq!((system.timers.frames(), system.timers.cycles()), (11, 11 * per_frame));
+    }
+
+    #[test]
+    fn does_not_trace_the_cycles_replayed() {
+        let (mut system, mut rewind) = (system(), Rewind::new(4, 100));
+        system.tracer = Some(Tracer::new(Box::new(io::sink())));
+        run(&mut system, &mut rewind, 12);
+        let cycles = system.timers.cycles();
+        assert_eq!(system.tracer.as_ref().unwrap().traced(), cycles);
+
+        rewind.step_back_frame(&mut system).unwrap();
+        rewind.step_back(&mut system).unwrap();
+        assert_eq!(system.tracer.as_ref().unwrap().traced(), cycles);
+
+        // Running again traces the instructions once more, as they are executed anew.
+        system.cycle().unwrap();
+        assert_eq!(system.tracer.as_ref().unwrap().traced(), cycles + 1);
+    }
+
+    #[test]
+    fn does_not_screenshot_the_frames_replayed() {
+        let (mut system, mut rewind) = (system(), Rewind::new(10, 100));
+        run(&mut system, &mut rewind, 12);
+
+        // Replaying from the snapshot at frame 0 to frame 7 goes through frame 5 again.
+        let file = std::env::temp_dir().join("emul8_rewind_camera.pbm");
+        let mut camera = Camera::new(Screenshot::new(), file.to_str().unwrap()).unwrap();
+        camera.set_frame(Some(5));
+        system.camera = Some(camera);
+        let per_frame = system.timers.clock_speed() as u64 / 60;
+        rewind.seek(&mut system, 7 * per_frame).unwrap();
+        assert_eq!(system.timers.frames(), 7);
+        assert!(!system.camera.as_ref().unwrap().is_taken());
+    }
+
+    #[test]
+    fn does_not_record_the_frames_replayed() {
+        let (mut system, mut rewind) = (system(), Rewind::new(4, 100));
+        system.recorder = Some(Recorder::new(Screenshot::new(), "unused.gif").unwrap());
+        run(&mut system, &mut rewind, 12);
+        assert_eq!(system.recorder.as_ref().unwrap().duration(), 12);
+
+        rewind.step_back_frame(&mut system).unwrap();
+        rewind.step_back_frame(&mut system).unwrap();
+        assert_eq!(system.recorder.as_ref().unwrap().duration(), 12);
+    }
+
+    #[test]
+    fn drops_the_history_rewound_over_and_the_oldest_snapshots() {
+        let (mut system, mut rewind) = (system(), Rewind::new(2, 3));
+        run(&mut system, &mut rewind, 12);
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.oldest(), Some(60));
+
+        // Taking another path after rewinding replaces the old one.
+        rewind.seek(&mut system, 70).unwrap();
+        system.keypad.press(5);
+        rewind.record(&system);
+        assert_eq!(rewind.len(), 1);
+        while system.timers.cycles() < 100 {
+            system.cycle().unwrap();
+        }
+        let state = system.save_state();
+
+        rewind.record(&system);
+        rewind.seek(&mut system, 60).unwrap();
+        rewind.seek(&mut system, 100).unwrap();
+        assert_eq!(system.save_state(), state);
+        assert_eq!(rewind.seek(&mut system, 59), Err(OutOfHistoryError));
+    }
+}
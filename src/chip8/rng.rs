@@ -0,0 +1,55 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of random bytes for the `RND Vx, byte` instruction.
+///
+/// The whole state of a generator must fit in a `u64`, so that it can be captured and restored,
+/// which makes runs reproducible end to end.
+pub trait Rng {
+    fn next_byte(&mut self) -> u8;
+
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+}
+
+/// SplitMix64 generator; every 64-bit seed is a valid starting state.
+pub struct SplitMix64 {
+    state: u64
+}
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed
+        }
+    }
+
+    /// Creates a generator with a non-deterministic seed.
+    pub fn from_entropy() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        if let Ok(time) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            hasher.write_u128(time.as_nanos());
+        }
+        Self::new(hasher.finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+impl Rng for SplitMix64 {
+    fn next_byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+    fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,300 @@
+//! Screenshots of the display, exported as PNG, netpbm (PBM and PGM) or plain text images.
+
+use crate::chip8::{Chip8, Display};
+use crate::chip8::checksum::crc32;
+use crate::chip8::deflate::zlib;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+/// An RGB colour.
+pub type Color = [u8; 3];
+
+/// Colours the pixels are drawn in by default, indexed by the colour of a pixel; the same as those
+/// of the terminal.
+pub const DEFAULT_PALETTE: [Color; 4] = [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF], [0xFF, 0x87, 0x00], [0x87, 0x5F, 0x00]];
+
+/// The formats screenshots are exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Indexed colour PNG, in the colours of the palette.
+    Png,
+    /// Binary PBM, each pixel black or white depending on how dark its colour is.
+    Pbm,
+    /// Binary PGM, each pixel the brightness of its colour.
+    Pgm,
+    /// The display as dumped by `Display::dump`, unscaled.
+    Text
+}
+impl ImageFormat {
+    /// The format a file is in, by its extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        extension.to_ascii_lowercase().parse().ok()
+    }
+}
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(ImageFormat::Png),
+            "pbm" => Ok(ImageFormat::Pbm),
+            "pgm" => Ok(ImageFormat::Pgm),
+            "txt" | "text" => Ok(ImageFormat::Text),
+            _ => Err(format!("unknown image format `{}`", s))
+        }
+    }
+}
+
+/// Exports the display to images, scaled up and in the colours of a palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+    scale: usize,
+    palette: [Color; 4]
+}
+impl Screenshot {
+    pub fn new() -> Self {
+        Self { scale: 1, palette: DEFAULT_PALETTE }
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+    /// Draws every pixel of the display as a square of `scale` by `scale` pixels, at least 1.
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.max(1);
+    }
+    pub fn palette(&self) -> [Color; 4] {
+        self.palette
+    }
+    /// Draws the pixels in the colours of `palette`, indexed by the colour of a pixel.
+    pub fn set_palette(&mut self, palette: [Color; 4]) {
+        self.palette = palette;
+    }
+    /// Draws the pixels that are off, and on, in the given colours.
+    pub fn set_colors(&mut self, off: Color, on: Color) {
+        self.palette[0] = off;
+        self.palette[1] = on;
+    }
+
+    /// Exports `display` in `format`.
+    pub fn encode(&self, display: &Display, format: ImageFormat) -> Vec<u8> {
+        match format {
+            ImageFormat::Png => self.png(display),
+            ImageFormat::Pbm => self.pbm(display),
+            ImageFormat::Pgm => self.pgm(display),
+            ImageFormat::Text => self.text(display).into_bytes()
+        }
+    }
+
+    /// Saves `display` to `file`, in the format of its extension.
+    pub fn save_file(&self, display: &Display, file: &str) -> io::Result<()> {
+        let format = ImageFormat::from_path(file).ok_or_else(unknown_format)?;
+        fs::write(file, self.encode(display, format))
+    }
+
+    pub fn png(&self, display: &Display) -> Vec<u8> {
+        let (width, height, pixels) = self.pixels(display);
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        // 8 bits per pixel, indexed colour, default compression, filtering and no interlacing.
+        header.extend_from_slice(&[8, 3, 0, 0, 0]);
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"PLTE", &self.palette.concat());
+        png_chunk(&mut png, b"IDAT", &zlib(&png_scanlines(&pixels, width)));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    pub fn pbm(&self, display: &Display) -> Vec<u8> {
+        let (width, height, pixels) = self.pixels(display);
+        let mut pbm = format!("P4\n{} {}\n", width, height).into_bytes();
+        // Rows are padded to whole bytes, and a set bit is black.
+        for row in pixels.chunks(width) {
+            for byte in row.chunks(8) {
+                let bits = byte.iter().enumerate()
+                    .filter(|(_, color)| brightness(self.palette[**color as usize]) < 128)
+                    .fold(0, |bits, (i, _)| bits | 0x80 >> i);
+                pbm.push(bits);
+            }
+        }
+        pbm
+    }
+
+    pub fn pgm(&self, display: &Display) -> Vec<u8> {
+        let (width, height, pixels) = self.pixels(display);
+        let mut pgm = format!("P5\n{} {}\n255\n", width, height).into_bytes();
+        pgm.extend(pixels.iter().map(|color| brightness(self.palette[*color as usize])));
+        pgm
+    }
+
+    /// The display as text, neither scaled nor coloured.
+    pub fn text(&self, display: &Display) -> String {
+        display.dump()
+    }
+
+    /// The width and height of the image of `display`, and the colours of its pixels row by row.
+    fn pixels(&self, display: &Display) -> (usize, usize, Vec<u8>) {
+        let (width, height) = (display.width() * self.scale, display.height() * self.scale);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| display.color(x / self.scale, y / self.scale))
+            .collect();
+        (width, height, pixels)
+    }
+}
+impl Default for Screenshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Appends a chunk to a PNG file: its length, type, data and CRC.
+pub(crate) fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// The rows of an image of one byte per pixel, each preceded by its filter type, none.
+pub(crate) fn png_scanlines(pixels: &[u8], width: usize) -> Vec<u8> {
+    pixels.chunks(width).flat_map(|row| std::iter::once(0).chain(row.iter().copied())).collect()
+}
+
+/// The brightness of `color`, from 0 to 255, weighted as perceived.
+fn brightness(color: Color) -> u8 {
+    let [r, g, b] = color.map(|c| c as u32);
+    ((r * 299 + g * 587 + b * 114) / 1000) as u8
+}
+
+fn unknown_format() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "unknown image format, expected a .png, .pbm, .pgm or .txt file")
+}
+
+/// Parses a colour as 6 hexadecimal digits, optionally preceded by `#`.
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    let digits = s.strip_prefix('#').unwrap_or(s);
+    let color = u32::from_str_radix(digits, 16).ok().filter(|_| digits.len() == 6)
+        .ok_or_else(|| format!("invalid colour `{}`, expected RRGGBB", s))?;
+    let [_, r, g, b] = color.to_be_bytes();
+    Ok([r, g, b])
+}
+
+/// Saves a screenshot of the machine it is attached to after a given frame, or when it is
+/// finished with.
+pub struct Camera {
+    screenshot: Screenshot,
+    file: String,
+    /// The frame after which the screenshot is taken, counted since power-on.
+    frame: Option<u64>,
+    taken: bool,
+    error: Option<io::Error>
+}
+impl Camera {
+    /// Creates a camera saving to `file`, in the format of its extension.
+    pub fn new(screenshot: Screenshot, file: &str) -> io::Result<Self> {
+        if ImageFormat::from_path(file).is_none() {
+            return Err(unknown_format());
+        }
+        Ok(Self { screenshot, file: file.to_string(), frame: None, taken: false, error: None })
+    }
+
+    pub fn frame(&self) -> Option<u64> {
+        self.frame
+    }
+    /// Takes the screenshot once `frame` frames have run since power-on, if given, instead of when
+    /// finished with.
+    pub fn set_frame(&mut self, frame: Option<u64>) {
+        self.frame = frame;
+    }
+    pub fn is_taken(&self) -> bool {
+        self.taken
+    }
+
+    /// Takes the screenshot if `system` reached the frame to take it at; called by the machine at
+    /// the end of every frame.
+    pub fn capture(&mut self, system: &Chip8) {
+        if !self.taken && self.frame == Some(system.timers.frames()) {
+            self.take(system);
+        }
+    }
+
+    /// Takes the screenshot if it wasn't taken yet, returning the error saving it, if any.
+    pub fn finish(mut self, system: &Chip8) -> io::Result<()> {
+        match self.frame {
+            Some(frame) if !self.taken => {
+                return Err(io::Error::new(io::ErrorKind::Other, format!("the run ended before frame {}", frame)));
+            },
+            None => self.take(system),
+            _ => { }
+        }
+        self.error.map_or(Ok(()), Err)
+    }
+
+    fn take(&mut self, system: &Chip8) {
+        self.taken = true;
+        self.error = self.screenshot.save_file(&system.display, &self.file).err();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A display with its corners lit up, in colours 1 to 3 but the top left.
+    fn display() -> Display {
+        let mut display = Display::new();
+        display.set_planes(0x3);
+        display.draw_sprite(63, 31, &[0x80, 0x80], false);
+        display.set_planes(0x1);
+        display.draw_sprite(63, 0, &[0x80], false);
+        display.set_planes(0x2);
+        display.draw_sprite(0, 31, &[0x80], false);
+        display
+    }
+
+    #[test]
+    fn exports_png() {
+        let mut screenshot = Screenshot::new();
+        screenshot.set_scale(2);
+        screenshot.set_colors([0x10, 0x20, 0x30], [0xE0, 0xF0, 0xFF]);
+        let png = screenshot.png(&display());
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[8..33], b"\0\0\0\x0DIHDR\0\0\0\x80\0\0\0\x40\x08\x03\0\0\0\xE5\x08\x8F\x4D");
+        assert_eq!(&png[33..57], b"\0\0\0\x0CPLTE\x10\x20\x30\xE0\xF0\xFF\xFF\x87\x00\x87\x5F\x00\xBE\xAE\x83\xC4");
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xAE\x42\x60\x82");
+    }
+
+    #[test]
+    fn exports_netpbm() {
+        let screenshot = Screenshot::new();
+        let pbm = screenshot.pbm(&display());
+        assert_eq!(&pbm[..9], b"P4\n64 32\n");
+        assert_eq!(pbm.len(), 9 + 8 * 32);
+        // Black is set; the orange of colour 2 is bright, and the brown of colour 3 dark.
+        assert_eq!((pbm[9], pbm[9 + 7]), (0xFF, 0xFE));
+        assert_eq!((pbm[9 + 31 * 8], pbm[9 + 31 * 8 + 7]), (0x7F, 0xFF));
+
+        let pgm = screenshot.pgm(&display());
+        assert_eq!(&pgm[..13], b"P5\n64 32\n255\n");
+        assert_eq!((pgm[13], pgm[13 + 63], pgm[13 + 31 * 64], pgm[13 + 32 * 64 - 1]), (0, 255, 155, 96));
+    }
+
+    #[test]
+    fn parses_formats_and_colours() {
+        assert_eq!(ImageFormat::from_path("shots/pong.PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path("pong.txt"), Some(ImageFormat::Text));
+        assert_eq!(ImageFormat::from_path("pong"), None);
+        assert_eq!(parse_color("#FF8000"), Ok([0xFF, 0x80, 0x00]));
+        assert_eq!(parse_color("102030"), Ok([0x10, 0x20, 0x30]));
+        assert!(parse_color("fff").is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,360 @@
+//! The binary format of save states.
+//!
+//! A snapshot starts with a header of 20 bytes, all numbers in it being big-endian:
+//!
+//! | Offset | Size | Contents                                                       |
+//! |--------|------|----------------------------------------------------------------|
+//! | 0      | 8    | `SNAPSHOT_MAGIC`                                               |
+//! | 8      | 2    | Version of the format the snapshot was written in              |
+//! | 10     | 2    | Oldest version of the format able to read the snapshot         |
+//! | 12     | 4    | Length of the payload                                          |
+//! | 16     | 4    | CRC-32 of the payload                                          |
+//!
+//! The payload is a sequence of sections, each a 4 byte tag followed by the length of its data as
+//! a 4 byte number, and the data itself.
+//!
+//! To keep older readers working, new state goes into new sections, which they skip, or at the
+//! end of an existing section, which they stop reading before. Only changes older readers
+//! can't cope with raise the compatible version.
+
+use crate::chip8::checksum::crc32;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use self::SnapshotError::*;
+
+/// Magic bytes every snapshot starts with.
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"EMUL8SAV";
+/// Version of the format written.
+pub const SNAPSHOT_VERSION: u16 = 1;
+/// Oldest version of the format able to read what is written.
+pub const SNAPSHOT_COMPATIBLE_VERSION: u16 = 1;
+
+const HEADER_SIZE: usize = 20;
+
+/// Builds a snapshot out of sections.
+pub struct SnapshotWriter {
+    payload: Vec<u8>
+}
+impl SnapshotWriter {
+    pub fn new() -> Self {
+        Self {
+            payload: Vec::new()
+        }
+    }
+
+    /// Adds the section `tag`, with the data written by `write`.
+    pub fn section<F: FnOnce(&mut SectionWriter)>(&mut self, tag: [u8; 4], write: F) {
+        let mut section = SectionWriter { data: Vec::new() };
+        write(&mut section);
+
+        self.payload.extend_from_slice(&tag);
+        self.payload.extend_from_slice(&(section.data.len() as u32).to_be_bytes());
+        self.payload.extend_from_slice(&section.data);
+    }
+
+    /// Prepends the header to the sections.
+    pub fn finish(self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        data.extend_from_slice(&SNAPSHOT_MAGIC);
+        data.extend_from_slice(&SNAPSHOT_VERSION.to_be_bytes());
+        data.extend_from_slice(&SNAPSHOT_COMPATIBLE_VERSION.to_be_bytes());
+        data.extend_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        data.extend_from_slice(&crc32(&self.payload).to_be_bytes());
+        data.extend_from_slice(&self.payload);
+        data
+    }
+}
+impl Default for SnapshotWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct SectionWriter {
+    data: Vec<u8>
+}
+impl SectionWriter {
+    pub fn u8(&mut self, val: u8) {
+        self.data.push(val);
+    }
+    pub fn bool(&mut self, val: bool) {
+        self.data.push(val as u8);
+    }
+    pub fn u16(&mut self, val: u16) {
+        self.data.extend_from_slice(&val.to_be_bytes());
+    }
+    pub fn u32(&mut self, val: u32) {
+        self.data.extend_from_slice(&val.to_be_bytes());
+    }
+    pub fn u64(&mut self, val: u64) {
+        self.data.extend_from_slice(&val.to_be_bytes());
+    }
+    /// Writes `val` preceded by its length.
+    pub fn bytes(&mut self, val: &[u8]) {
+        self.u32(val.len() as u32);
+        self.data.extend_from_slice(val);
+    }
+}
+
+/// A snapshot whose header and checksum have been verified.
+pub struct Snapshot<'a> {
+    version: u16,
+    sections: HashMap<[u8; 4], &'a [u8]>
+}
+impl<'a> Snapshot<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, SnapshotError> {
+        if data.len() < SNAPSHOT_MAGIC.len() || data[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+            return Err(InvalidMagicError);
+        }
+        if data.len() < HEADER_SIZE {
+            return Err(TruncatedError);
+        }
+
+        let number = |offset: usize, len: usize| data[offset..(offset + len)].iter().fold(0u32, |n, byte| (n << 8) | *byte as u32);
+        let version = number(8, 2) as u16;
+        let compatible_version = number(10, 2) as u16;
+        if compatible_version > SNAPSHOT_VERSION {
+            return Err(UnsupportedVersionError { version });
+        }
+
+        let payload = &data[HEADER_SIZE..];
+        if payload.len() != number(12, 4) as usize {
+            return Err(TruncatedError);
+        }
+        let (expected, actual) = (number(16, 4), crc32(payload));
+        if expected != actual {
+            return Err(ChecksumError { expected, actual });
+        }
+
+        let mut sections = HashMap::new();
+        let mut rest = payload;
+        while !rest.is_empty() {
+            if rest.len() < 8 {
+                return Err(TruncatedError);
+            }
+            let tag = [rest[0], rest[1], rest[2], rest[3]];
+            let len = u32::from_be_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+            if rest.len() - 8 < len {
+                return Err(TruncatedError);
+            }
+            sections.insert(tag, &rest[8..(8 + len)]);
+            rest = &rest[(8 + len)..];
+        }
+
+        Ok(Self { version, sections })
+    }
+
+    /// Version of the format the snapshot was written in.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn section(&self, tag: [u8; 4]) -> Result<SectionReader<'a>, SnapshotError> {
+        match self.sections.get(&tag) {
+            Some(data) => Ok(SectionReader { data, tag }),
+            None => Err(MissingSectionError { tag })
+        }
+    }
+}
+
+pub struct SectionReader<'a> {
+    data: &'a [u8],
+    tag: [u8; 4]
+}
+impl<'a> SectionReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.data.len() < len {
+            return Err(TruncatedSectionError { tag: self.tag });
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    /// Whether everything in the section has been read; values added to the end of a section in
+    /// later versions of the format are only read if they are there.
+    pub fn is_at_end(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+    pub fn bool(&mut self) -> Result<bool, SnapshotError> {
+        Ok(self.u8()? != 0)
+    }
+    pub fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+    /// Reads bytes written along with their length.
+    pub fn bytes(&mut self) -> Result<&'a [u8], SnapshotError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+    /// Reads exactly `N` bytes written along with their length.
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        let bytes = self.bytes()?;
+        let mut array = [0; N];
+        if bytes.len() != N {
+            return Err(self.invalid());
+        }
+        array.copy_from_slice(bytes);
+        Ok(array)
+    }
+
+    /// The error for a value out of the range of what the section can hold.
+    pub fn invalid(&self) -> SnapshotError {
+        InvalidValueError { tag: self.tag }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The data does not start with `SNAPSHOT_MAGIC`; it is not a snapshot.
+    InvalidMagicError,
+    /// The snapshot was written in a *version* of the format too new to be read.
+    UnsupportedVersionError { version: u16 },
+    /// The payload does not match its checksum.
+    ChecksumError { expected: u32, actual: u32 },
+    /// The snapshot ends early.
+    TruncatedError,
+    /// A section needed to restore the machine is not in the snapshot.
+    MissingSectionError { tag: [u8; 4] },
+    /// A section ends before all of its values could be read.
+    TruncatedSectionError { tag: [u8; 4] },
+    /// A section holds a value the machine can't be restored to.
+    InvalidValueError { tag: [u8; 4] }
+}
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |tag: &[u8; 4]| String::from_utf8_lossy(tag).trim_end().to_string();
+        match self {
+            InvalidMagicError => write!(f, "not a save state"),
+            UnsupportedVersionError { version } => write!(f, "save state version {} is not supported", version),
+            ChecksumError { expected, actual } => write!(f, "save state is corrupt (checksum {:#010X}, expected {:#010X})", actual, expected),
+            TruncatedError => write!(f, "save state is truncated"),
+            MissingSectionError { tag } => write!(f, "save state has no `{}` section", name(tag)),
+            TruncatedSectionError { tag } => write!(f, "`{}` section of the save state is truncated", name(tag)),
+            InvalidValueError { tag } => write!(f, "`{}` section of the save state holds an invalid value", name(tag))
+        }
+    }
+}
+impl Error for SnapshotError {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{boot, Chip8, RunState, Variant};
+
+    fn system() -> Chip8 {
+        let mut system = boot(Variant::XoChip, "
+            : main
+                hires
+                loop
+                    v0 := random 0x7F
+                    v1 := random 0x3F
+                    i := hex v0
+                    sprite v0 v1 5
+                    v2 += 1
+                    i := long 0x1000
+                    save v2
+                again
+        ");
+        system.seed(42);
+        system
+    }
+
+    /// Updates the length and checksum in the header of `data` to its payload.
+    fn reseal(data: &mut [u8]) {
+        let payload_len = (data.len() - HEADER_SIZE) as u32;
+        let checksum = crc32(&data[HEADER_SIZE..]);
+        data[12..16].copy_from_slice(&payload_len.to_be_bytes());
+        data[16..20].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    #[test]
+    fn restored_machine_runs_on_identically() {
+        let mut original = system();
+        for _ in 0..5 {
+            original.run_frame().unwrap();
+        }
+        original.keypad.press(0xA);
+        let state = original.save_state();
+
+        let mut restored = Chip8::new();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.variant, Variant::XoChip);
+        assert!(restored.keypad.is_pressed(0xA));
+
+        for _ in 0..5 {
+            original.run_frame().unwrap();
+            restored.run_frame().unwrap();
+        }
+        assert_eq!(restored.save_state(), original.save_state());
+        assert_eq!(restored.display.frame(), original.display.frame());
+    }
+
+    #[test]
+    fn restores_faulted_machine_halted() {
+        let mut system = system();
+        system.memory.copy(0x200, &[0xFF, 0xFF]).unwrap();
+        assert!(system.cycle().is_err());
+
+        let mut restored = Chip8::new();
+        restored.load_state(&system.save_state()).unwrap();
+        assert_eq!(restored.state(), RunState::Halted);
+    }
+
+    #[test]
+    fn rejects_damaged_snapshots_without_touching_the_machine() {
+        let state = system().save_state();
+        let mut target = Chip8::new();
+        let untouched = target.save_state();
+
+        let mut corrupt = state.clone();
+        *corrupt.last_mut().unwrap() ^= 0xFF;
+        assert!(matches!(target.load_state(&corrupt), Err(ChecksumError { .. })));
+        assert_eq!(target.load_state(&state[..(state.len() - 1)]), Err(TruncatedError));
+        assert_eq!(target.load_state(b"EMUL8ROM and more"), Err(InvalidMagicError));
+
+        let mut too_new = state.clone();
+        too_new[8..12].copy_from_slice(&[0, 7, 0, 5]);
+        assert_eq!(target.load_state(&too_new), Err(UnsupportedVersionError { version: 7 }));
+
+        let mut invalid = state;
+        let registers = invalid.windows(4).position(|tag| tag == b"CPU ").unwrap();
+        invalid[registers + 10] = 16;
+        reseal(&mut invalid);
+        assert_eq!(target.load_state(&invalid), Err(InvalidValueError { tag: *b"CPU " }));
+
+        assert_eq!(target.save_state(), untouched);
+    }
+
+    #[test]
+    fn reads_snapshots_of_newer_compatible_versions() {
+        let original = system();
+        let mut state = original.save_state();
+        state[8..10].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_be_bytes());
+        state.extend_from_slice(b"NEW \0\0\0\x03abc");
+        reseal(&mut state);
+
+        let mut restored = Chip8::new();
+        restored.load_state(&state).unwrap();
+        assert_eq!(Snapshot::parse(&state).unwrap().version(), SNAPSHOT_VERSION + 1);
+        assert_eq!(restored.save_state(), original.save_state());
+    }
+}
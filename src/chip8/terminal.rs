@@ -0,0 +1,248 @@
+//! Rendering of the display to a terminal, with ANSI escape codes and Unicode block or braille
+//! characters.
+
+use crate::chip8::{Chip8, Display};
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Colours the pixels are drawn in, indexed by the colour of a pixel, as codes of the 256 colour
+/// ANSI palette.
+pub const PALETTE: [u8; 4] = [16, 231, 208, 94];
+
+/// The characters a display is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Glyphs {
+    /// `▀`, a cell showing the pixel above in its foreground colour and the one below in its
+    /// background colour.
+    #[default]
+    HalfBlock,
+    /// Braille patterns, a cell showing 2x4 pixels in a single colour.
+    Braille
+}
+impl Glyphs {
+    /// The width and height in pixels of a cell.
+    pub fn cell_size(&self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4)
+        }
+    }
+
+    /// The cell at (`column`, `row`) of `display`.
+    fn cell(&self, display: &Display, column: usize, row: usize) -> Cell {
+        let (width, height) = self.cell_size();
+        let color = |x: usize, y: usize| {
+            let (x, y) = (column * width + x, row * height + y);
+            if x < display.width() && y < display.height() { display.color(x, y) } else { 0 }
+        };
+
+        match self {
+            Glyphs::HalfBlock => Cell { glyph: '▀', fg: color(0, 0), bg: color(0, 1) },
+            Glyphs::Braille => {
+                // Dots are numbered down the left column first, with the bottom row last.
+                const DOTS: [(usize, usize); 8] = [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2), (0, 3), (1, 3)];
+                let (mut dots, mut fg) = (0, 0);
+                for (bit, (x, y)) in DOTS.iter().enumerate() {
+                    let color = color(*x, *y);
+                    if color != 0 {
+                        dots |= 1 << bit;
+                        fg = fg.max(color);
+                    }
+                }
+                Cell { glyph: char::from_u32(0x2800 + dots).unwrap(), fg, bg: 0 }
+            }
+        }
+    }
+}
+impl FromStr for Glyphs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" | "half-block" => Ok(Glyphs::HalfBlock),
+            "braille" => Ok(Glyphs::Braille),
+            _ => Err(format!("unknown glyphs `{}`", s))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    glyph: char,
+    /// Colours of the pixels, indices into `PALETTE`.
+    fg: u8,
+    bg: u8
+}
+
+/// Draws the display of a machine to a terminal, along with its registers if asked to, redrawing
+/// only what changed since the last time.
+pub struct TerminalRenderer {
+    glyphs: Glyphs,
+    panel: bool,
+    /// Size in cells of the display last drawn.
+    columns: usize,
+    rows: usize,
+    /// The cells on the terminal, row by row; empty if it needs redrawing entirely.
+    cells: Vec<Cell>,
+    /// The lines of the side panel on the terminal.
+    panel_lines: Vec<String>
+}
+impl TerminalRenderer {
+    pub fn new(glyphs: Glyphs) -> Self {
+        Self {
+            glyphs,
+            panel: false,
+            columns: 0,
+            rows: 0,
+            cells: Vec::new(),
+            panel_lines: Vec::new()
+        }
+    }
+
+    pub fn glyphs(&self) -> Glyphs {
+        self.glyphs
+    }
+    pub fn has_panel(&self) -> bool {
+        self.panel
+    }
+    /// Shows the registers in a panel to the right of the display.
+    pub fn set_panel(&mut self, panel: bool) {
+        self.panel = panel;
+        self.invalidate();
+    }
+
+    /// Makes the next render redraw everything, e.g. after the terminal was cleared.
+    pub fn invalidate(&mut self) {
+        self.cells.clear();
+        self.panel_lines.clear();
+    }
+
+    /// Returns the escape codes and characters updating the terminal to show `system`, starting
+    /// from what was last rendered; nothing if it didn't change.
+    pub fn render(&mut self, system: &Chip8) -> String {
+        let display = &system.display;
+        let (width, height) = self.glyphs.cell_size();
+        let (columns, rows) = ((display.width() + width - 1) / width, (display.height() + height - 1) / height);
+
+        let mut out = String::new();
+        if (columns, rows) != (self.columns, self.rows) || self.cells.is_empty() {
+            out.push_str("\x1b[0m\x1b[2J");
+            self.invalidate();
+            self.columns = columns;
+            self.rows = rows;
+        }
+
+        let cells: Vec<Cell> = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| self.glyphs.cell(display, column, row))
+            .collect();
+        // The cursor and colours are only moved and set when they aren't where they need to be.
+        let (mut cursor, mut colors) = (None, None);
+        for (i, cell) in cells.iter().enumerate() {
+            if self.cells.get(i) == Some(cell) {
+                continue;
+            }
+            let (row, column) = (i / columns, i % columns);
+            if cursor != Some((row, column)) {
+                write!(out, "\x1b[{};{}H", row + 1, column + 1).unwrap();
+            }
+            if colors != Some((cell.fg, cell.bg)) {
+                write!(out, "\x1b[38;5;{};48;5;{}m", PALETTE[cell.fg as usize], PALETTE[cell.bg as usize]).unwrap();
+                colors = Some((cell.fg, cell.bg));
+            }
+            out.push(cell.glyph);
+            cursor = Some((row, column + 1));
+        }
+        if colors.is_some() {
+            out.push_str("\x1b[0m");
+        }
+        self.cells = cells;
+
+        if self.panel {
+            let lines: Vec<String> = system.processor.registers().dump().lines().map(str::to_string).collect();
+            for (i, line) in lines.iter().enumerate() {
+                if self.panel_lines.get(i) != Some(line) {
+                    write!(out, "\x1b[{};{}H{}\x1b[K", i + 1, columns + 3, line).unwrap();
+                }
+            }
+            self.panel_lines = lines;
+        }
+        out
+    }
+}
+
+/// The key of the keypad a key of the host keyboard is mapped to, laid out the same on a QWERTY
+/// keyboard:
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// Q W E R  ->  4 5 6 D
+/// A S D F      7 8 9 E
+/// Z X C V      A 0 B F
+/// ```
+pub fn keypad_key(key: char) -> Option<u8> {
+    match key.to_ascii_lowercase() {
+        '1' => Some(0x1), '2' => Some(0x2), '3' => Some(0x3), '4' => Some(0xC),
+        'q' => Some(0x4), 'w' => Some(0x5), 'e' => Some(0x6), 'r' => Some(0xD),
+        'a' => Some(0x7), 's' => Some(0x8), 'd' => Some(0x9), 'f' => Some(0xE),
+        'z' => Some(0xA), 'x' => Some(0x0), 'c' => Some(0xB), 'v' => Some(0xF),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Variant;
+
+    fn system() -> Chip8 {
+        Chip8::with_variant(Variant::XoChip, Variant::XoChip.quirks())
+    }
+
+    #[test]
+    fn redraws_only_the_changed_cells() {
+        let mut system = system();
+        let mut renderer = TerminalRenderer::new(Glyphs::HalfBlock);
+
+        let full = renderer.render(&system);
+        assert!(full.starts_with("\x1b[0m\x1b[2J\x1b[1;1H\x1b[38;5;16;48;5;16m▀▀"));
+        assert_eq!(full.matches('▀').count(), 64 * 16);
+        assert_eq!(renderer.render(&system), "");
+
+        // Lights up (3, 1) and (4, 1), at the bottom of the cells of the first row.
+        system.display.draw_sprite(3, 1, &[0b1100_0000], false);
+        assert_eq!(renderer.render(&system), "\x1b[1;4H\x1b[38;5;16;48;5;231m▀▀\x1b[0m");
+
+        system.display.set_hires(true);
+        assert_eq!(renderer.render(&system).matches('▀').count(), 128 * 32);
+    }
+
+    #[test]
+    fn draws_braille_patterns_in_the_brightest_colour() {
+        let mut system = system();
+        let mut renderer = TerminalRenderer::new(Glyphs::Braille);
+        renderer.render(&system);
+
+        system.display.draw_sprite(0, 0, &[0b1000_0000, 0, 0, 0b0100_0000], false);
+        system.display.set_planes(0x2);
+        system.display.draw_sprite(0, 3, &[0b1000_0000], false);
+        assert_eq!(renderer.render(&system), "\x1b[1;1H\x1b[38;5;208;48;5;16m\u{28C1}\x1b[0m");
+    }
+
+    #[test]
+    fn updates_the_changed_lines_of_the_panel() {
+        let mut system = system();
+        let mut renderer = TerminalRenderer::new(Glyphs::HalfBlock);
+        renderer.set_panel(true);
+        assert!(renderer.render(&system).contains("\x1b[1;67HPC  0x200\x1b[K"));
+
+        system.processor.registers_mut().step_pc();
+        assert_eq!(renderer.render(&system), "\x1b[1;67HPC  0x202\x1b[K");
+    }
+
+    #[test]
+    fn maps_the_keyboard_to_the_keypad() {
+        let keys: Vec<Option<u8>> = "1qazXCv5".chars().map(keypad_key).collect();
+        assert_eq!(keys, vec![Some(0x1), Some(0x4), Some(0x7), Some(0xA), Some(0x0), Some(0xB), Some(0xF), None]);
+    }
+}
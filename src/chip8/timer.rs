@@ -0,0 +1,122 @@
+use crate::chip8::snapshot::{SectionReader, SectionWriter, SnapshotError};
+
+/// Rate at which the delay and sound timers count down, in Hz.
+pub const TIMER_FREQUENCY: u32 = 60;
+/// Default number of instructions executed per second of emulated time.
+pub const DEFAULT_CLOCK_SPEED: u32 = 600;
+
+/// Keeps track of emulated time, so that the delay and sound timers tick at 60 Hz regardless of
+/// the number of instructions the processor executes per second.
+pub struct Timers {
+    /// Instructions executed per second of emulated time.
+    clock_speed: u32,
+    /// Progress towards the next tick, in units of 1 / (`clock_speed` * `TIMER_FREQUENCY`) seconds.
+    elapsed: u32,
+    /// Number of 60 Hz ticks (frames) since power-on.
+    frames: u64,
+    /// Number of processor cycles since power-on.
+    cycles: u64
+}
+impl Timers {
+    pub fn new() -> Self {
+        Self {
+            clock_speed: DEFAULT_CLOCK_SPEED,
+            elapsed: 0,
+            frames: 0,
+            cycles: 0
+        }
+    }
+
+    pub fn clock_speed(&self) -> u32 {
+        self.clock_speed
+    }
+    pub fn set_clock_speed(&mut self, clock_speed: u32) {
+        self.clock_speed = clock_speed.max(1);
+        self.elapsed = self.elapsed.min(self.clock_speed - 1);
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Advances emulated time by the duration of a single instruction.
+    ///
+    /// Returns whether a 60 Hz boundary was crossed, in which case the timers should tick.
+    pub fn step(&mut self) -> bool {
+        self.cycles += 1;
+        self.elapsed += TIMER_FREQUENCY;
+        if self.elapsed >= self.clock_speed {
+            self.elapsed -= self.clock_speed;
+            self.frames += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.u32(self.clock_speed);
+        out.u32(self.elapsed);
+        out.u64(self.frames);
+        out.u64(self.cycles);
+    }
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, SnapshotError> {
+        let clock_speed = input.u32()?;
+        let elapsed = input.u32()?;
+        let frames = input.u64()?;
+        let cycles = if input.is_at_end() { 0 } else { input.u64()? };
+        if clock_speed == 0 || elapsed >= clock_speed {
+            return Err(input.invalid());
+        }
+        Ok(Self { clock_speed, elapsed, frames, cycles })
+    }
+}
+impl Default for Timers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn ticks_once_per_frame_whatever_the_clock_speed() {
+        // A second of emulated time is 60 frames, however many instructions each of them takes.
+        for clock_speed in [60, 600, 700, 1000, 1234] {
+            let mut timers = Timers::new();
+            timers.set_clock_speed(clock_speed);
+            let ticks = (0..clock_speed).filter(|_| timers.step()).count();
+            assert_eq!((ticks, timers.frames(), timers.cycles()), (60, 60, clock_speed as u64));
+        }
+
+        // At 600 instructions per second, every 10th instruction ends a frame.
+        let mut timers = Timers::new();
+        let ticks: Vec<u64> = (1..=30).filter(|_| timers.step()).collect();
+        assert_eq!(ticks, vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn counts_the_delay_and_sound_timers_down_once_per_frame() {
+        let mut system = Chip8::new();
+        system.init().unwrap();
+        // DT := ST := 5, then loop forever.
+        system.bios.load_rom(&mut system.memory, &[0x60, 0x05, 0xF0, 0x15, 0xF0, 0x18, 0x12, 0x06]).unwrap();
+
+        let mut timers = Vec::new();
+        for _ in 0..30 {
+            system.cycle().unwrap();
+            let registers = system.processor.registers();
+            timers.push((registers.delay_timer(), registers.sound_timer()));
+        }
+        assert_eq!(&timers[2..9], &[(5, 5); 7]);
+        assert_eq!(&timers[9..19], &[(4, 4); 10]);
+        assert_eq!(&timers[19..29], &[(3, 3); 10]);
+        assert_eq!(timers[29], (2, 2));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,203 @@
+//! Execution traces, logging every instruction executed along with the state it was executed in.
+//!
+//! Each instruction is traced on a line of its own, before it is executed, in fixed width
+//! columns separated by a space:
+//!
+//! ```text
+//! 0000000005 020A D015     DRW V0, V1, 5            V 0A 0C 3C 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0082 SP 00 DT 3C ST 00
+//! ```
+//!
+//! That is the number of cycles executed so far, the program counter, the instruction as it is in
+//! memory (8 digits for the 4 byte `LD I, long`), the instruction disassembled, registers V0 to VF,
+//! I, the stack pointer, and the delay and sound timers, all in uppercase hexadecimal but the cycle
+//! count. Cycles spent waiting for a key are not traced, as no instruction is executed.
+
+use crate::chip8::{Chip8, Processor};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+/// Writes a line for every instruction executed by a machine it is attached to.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    /// Addresses the instructions traced are at.
+    range: RangeInclusive<u16>,
+    /// Cycle from which instructions are traced.
+    start: u64,
+    /// Number of instructions traced at most.
+    limit: Option<u64>,
+    traced: u64,
+    /// The first error writing the trace, after which nothing more is written.
+    error: Option<io::Error>
+}
+impl Tracer {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            range: 0..=0xFFFF,
+            start: 0,
+            limit: None,
+            traced: 0,
+            error: None
+        }
+    }
+    /// Creates a tracer writing to `file`, replacing its contents.
+    pub fn to_file(file: &str) -> io::Result<Self> {
+        Ok(Self::new(Box::new(BufWriter::new(File::create(file)?))))
+    }
+
+    pub fn range(&self) -> RangeInclusive<u16> {
+        self.range.clone()
+    }
+    /// Only traces the instructions at addresses within `range`.
+    pub fn set_range(&mut self, range: RangeInclusive<u16>) {
+        self.range = range;
+    }
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+    /// Only traces the instructions executed from the cycle `start` on.
+    pub fn set_start(&mut self, start: u64) {
+        self.start = start;
+    }
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+    /// Stops tracing after `limit` instructions, if given.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    /// Number of instructions traced so far.
+    pub fn traced(&self) -> u64 {
+        self.traced
+    }
+
+    /// Traces the instruction `system` is about to execute, if it passes the filters.
+    pub fn trace(&mut self, system: &Chip8) {
+        let pc = system.processor.registers().pc();
+        if self.error.is_some() || system.timers.cycles() < self.start || !self.range.contains(&pc) ||
+            self.limit.is_some_and(|limit| self.traced >= limit) {
+            return;
+        }
+
+        // An instruction that can't be fetched faults, which the machine reports by itself.
+        if let Some(line) = line(system) {
+            self.traced += 1;
+            if let Err(err) = writeln!(self.out, "{}", line) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    /// Flushes the trace, returning the first error writing it, if any.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.out.flush()
+        }
+    }
+}
+
+/// Formats the trace line of the instruction `system` is about to execute.
+pub fn line(system: &Chip8) -> Option<String> {
+    let registers = system.processor.registers();
+    let operation = Processor::peek_operation(system).ok()?;
+    let raw: String = operation.encode_bytes().iter().map(|byte| format!("{:02X}", byte)).collect();
+
+    let mut line = format!("{:010} {:04X} {:<8} {:<24} V", system.timers.cycles(), registers.pc(), raw, operation.to_string());
+    for x in 0..16 {
+        write!(line, " {:02X}", registers.read_v(x)).unwrap();
+    }
+    write!(line, " I {:04X} SP {:02X} DT {:02X} ST {:02X}", registers.i(), registers.sp(), registers.delay_timer(),
+        registers.sound_timer()).unwrap();
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{boot, Variant};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A buffer that can still be read once the tracer writing to it is attached to a machine.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(configure: impl FnOnce(&mut Tracer)) -> Vec<String> {
+        let mut system = boot(Variant::XoChip, "
+            : main
+                v0 := 10
+                i := long data
+                loop
+                    v0 += -1
+                    if v0 == 0 then jump done
+                again
+            : done
+                delay := v0
+                loop again
+            : data
+        ");
+
+        let out = Shared::default();
+        let mut tracer = Tracer::new(Box::new(out.clone()));
+        configure(&mut tracer);
+        system.tracer = Some(tracer);
+        for _ in 0..40 {
+            system.cycle().unwrap();
+        }
+        system.tracer.take().unwrap().finish().unwrap();
+
+        let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn formats_lines_as_documented() {
+        let mut system = boot(Variant::Chip8, "
+            : main
+                v0 := 10
+                v1 := 12
+                i := hex v0
+                v2 := 60
+                delay := v2
+                sprite v0 v1 5
+        ");
+        for _ in 0..5 {
+            system.cycle().unwrap();
+        }
+        // The example of the module documentation.
+        assert_eq!(line(&system).unwrap(), "0000000005 020A D015     DRW V0, V1, 5            V 0A 0C 3C 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0082 SP 00 DT 3C ST 00");
+    }
+
+    #[test]
+    fn traces_every_instruction() {
+        let lines = trace(|_| { });
+        assert_eq!(lines.len(), 40);
+        assert_eq!(lines[0], "0000000000 0200 600A     LD V0, 0x0A              V 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000 SP 00 DT 00 ST 00");
+        assert_eq!(lines[1], "0000000001 0202 F0000212 LD I, long 0x212         V 0A 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000 SP 00 DT 00 ST 00");
+        assert!(lines[2].starts_with("0000000002 0206 70FF     ADD V0, 0xFF             V 0A "));
+        assert!(lines[3].contains(" V 09 00 ") && lines[3].contains(" I 0212 "));
+    }
+
+    #[test]
+    fn filters_by_address_cycle_and_count() {
+        let lines = trace(|tracer| {
+            tracer.set_range(0x206..=0x206);
+            tracer.set_start(10);
+            tracer.set_limit(Some(3));
+        });
+        let columns: Vec<(&str, &str)> = lines.iter().map(|line| (&line[..10], &line[11..15])).collect();
+        assert_eq!(columns, vec![("0000000011", "0206"), ("0000000014", "0206"), ("0000000017", "0206")]);
+    }
+}
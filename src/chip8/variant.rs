@@ -0,0 +1,83 @@
+use crate::chip8::{Opcode, Quirks};
+use std::fmt;
+use std::str::FromStr;
+use crate::chip8::memory::{DEFAULT_MEMORY_SIZE, MAX_MEMORY_SIZE};
+use crate::chip8::snapshot::{SectionReader, SectionWriter, SnapshotError};
+
+/// The CHIP-8 dialect being emulated, which determines the instructions available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    /// The original CHIP-8 of the COSMAC VIP.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1, adding scrolling, a 128x64 high resolution mode, large sprites and fonts,
+    /// and the RPL user flags.
+    SuperChip,
+    /// XO-CHIP, extending SUPER-CHIP with 64 KiB of memory, two bitplanes for up to four colours,
+    /// and programmable audio.
+    XoChip
+}
+impl Variant {
+    /// The quirks the interpreters of this variant are usually emulated with.
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Variant::Chip8 => Quirks::cosmac_vip(),
+            Variant::SuperChip => Quirks::super_chip(),
+            Variant::XoChip => Quirks::xo_chip()
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Variant::Chip8 | Variant::SuperChip => DEFAULT_MEMORY_SIZE,
+            Variant::XoChip => MAX_MEMORY_SIZE
+        }
+    }
+
+    pub fn supports(&self, operation: &Opcode) -> bool {
+        match operation {
+            Opcode::_00Cn { .. } | Opcode::_00FB | Opcode::_00FC | Opcode::_00FD | Opcode::_00FE | Opcode::_00FF |
+            Opcode::_Fx30 { .. } | Opcode::_Fx75 { .. } | Opcode::_Fx85 { .. } => *self != Variant::Chip8,
+            Opcode::_5xy2 { .. } | Opcode::_5xy3 { .. } | Opcode::_F000 { .. } | Opcode::_Fn01 { .. } |
+            Opcode::_F002 | Opcode::_Fx3A { .. } => *self == Variant::XoChip,
+            _ => true
+        }
+    }
+
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.u8(match self {
+            Variant::Chip8 => 0,
+            Variant::SuperChip => 1,
+            Variant::XoChip => 2
+        });
+    }
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, SnapshotError> {
+        match input.u8()? {
+            0 => Ok(Variant::Chip8),
+            1 => Ok(Variant::SuperChip),
+            2 => Ok(Variant::XoChip),
+            _ => Err(input.invalid())
+        }
+    }
+}
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Variant::Chip8),
+            "schip" | "superchip" | "super-chip" => Ok(Variant::SuperChip),
+            "xochip" | "xo-chip" => Ok(Variant::XoChip),
+            _ => Err(format!("unknown variant `{}`", s))
+        }
+    }
+}
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Variant::Chip8 => "chip8",
+            Variant::SuperChip => "schip",
+            Variant::XoChip => "xochip"
+        })
+    }
+}
\ No newline at end of file
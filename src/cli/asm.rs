@@ -0,0 +1,16 @@
+use crate::cli::options::Options;
+use emul8::chip8::Assembler;
+use std::fs;
+
+pub fn asm(options: &Options) -> Result<(), String> {
+    let source_path = options.arg(0, "source")?;
+    let rom_path = options.arg(1, "rom")?;
+    let source = fs::read_to_string(source_path).map_err(|err| format!("{}: {}", source_path, err))?;
+
+    let assembly = Assembler::new(options.variant).assemble(&source)
+        .map_err(|err| format!("{}: {}", source_path, err))?;
+    fs::write(rom_path, &assembly.bytes).map_err(|err| format!("{}: {}", rom_path, err))?;
+
+    print!("{}", assembly.symbol_table());
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use crate::cli::options::{MovieMode, Options};
+use emul8::chip8::{Debugger, Disassembler};
+use emul8::chip8::debugger::{parse_number, StopReason, WatchKind};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  step [n]           (s) execute the next n instructions (default: 1)
+  next               (n) execute the next instruction, running through subroutine calls
+  finish             (f) run until the current subroutine returns
+  continue           (c) run until a breakpoint or watchpoint is hit
+  until <addr>       (u) run until the program counter reaches addr
+  back [n]               step back n processor cycles (default: 1)
+  back-frame [n]         step back to the start of the frame, n times (default: 1)
+  break <addr> [if <register> <op> <value>]
+                     (b) stop at addr, only if the condition holds if given; the register is
+                         one of v0-vf, i, sp, dt, st and the op one of == != < <= > >=
+  delete <addr>      (d) remove the breakpoint at addr
+  watch <addr> [r|w|rw]
+                     (w) stop after addr is read, written or either (default: w)
+  unwatch <addr>         remove the watchpoint at addr
+  list               (l) list the breakpoints and watchpoints
+  regs               (r) print the registers
+  mem <addr> [len]   (x) print len bytes of memory from addr (default: 16)
+  dis [addr]             list the instructions from addr (default: the program counter)
+  screen                 print the display
+  press <key>            press key 0-F
+  release <key>          release key 0-F
+  quit               (q) leave the debugger
+An empty line repeats the last command.
+";
+
+pub fn debug(options: &Options) -> Result<(), String> {
+    let mut debugger = Debugger::new(options.boot()?);
+    print_location(&debugger);
+
+    let stdin = io::stdin();
+    let mut last = String::new();
+    loop {
+        print!("(emul8) ");
+        io::stdout().flush().map_err(|err| err.to_string())?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).map_err(|err| err.to_string())? == 0 {
+            println!();
+            break;
+        }
+        if line.trim().is_empty() {
+            line = last.clone();
+        } else {
+            last = line.clone();
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.split_first() {
+            Some((&"quit", _)) | Some((&"q", _)) => break,
+            Some((command, args)) => {
+                if let Err(err) = execute(&mut debugger, command, args) {
+                    println!("error: {}", err);
+                }
+            },
+            None => { }
+        }
+    }
+
+    options.finish(&mut debugger.into_inner(), &mut MovieMode::Off)
+}
+
+fn execute(debugger: &mut Debugger, command: &str, args: &[&str]) -> Result<(), String> {
+    let reason = match command {
+        "step" | "s" => {
+            let count = args.first().map(|n| number(n)).transpose()?.unwrap_or(1);
+            let mut reason = StopReason::Done;
+            for _ in 0..count {
+                reason = debugger.step();
+                if reason != StopReason::Done {
+                    break;
+                }
+            }
+            reason
+        },
+        "next" | "n" => debugger.step_over(),
+        "finish" | "f" => debugger.step_out(),
+        "continue" | "c" => debugger.run(),
+        "until" | "u" => debugger.run_to(address(args)?),
+        "back" | "back-frame" => {
+            let count = args.first().map(|n| number(n)).transpose()?.unwrap_or(1);
+            for _ in 0..count {
+                let result = if command == "back" { debugger.step_back() } else { debugger.step_back_frame() };
+                result.map_err(|err| err.to_string())?;
+            }
+            StopReason::Done
+        },
+
+        "break" | "b" => {
+            let addr = address(args)?;
+            let condition = match args.get(1) {
+                Some(&"if") => Some(args[2..].join(" ").parse()?),
+                Some(word) => return Err(format!("expected `if`, found `{}`", word)),
+                None => None
+            };
+            debugger.set_breakpoint(addr, condition);
+            return Ok(());
+        },
+        "delete" | "d" => {
+            let addr = address(args)?;
+            if !debugger.remove_breakpoint(addr) {
+                return Err(format!("no breakpoint at {:#05X}", addr));
+            }
+            return Ok(());
+        },
+        "watch" | "w" => {
+            let addr = address(args)?;
+            let kind = match args.get(1).copied() {
+                Some("r") => WatchKind::Read,
+                Some("w") | None => WatchKind::Write,
+                Some("rw") => WatchKind::ReadWrite,
+                Some(kind) => return Err(format!("unknown watchpoint kind `{}`", kind))
+            };
+            debugger.set_watchpoint(addr, kind);
+            return Ok(());
+        },
+        "unwatch" => {
+            let addr = address(args)?;
+            if !debugger.remove_watchpoint(addr) {
+                return Err(format!("no watchpoint at {:#05X}", addr));
+            }
+            return Ok(());
+        },
+        "list" | "l" => {
+            for (addr, condition) in debugger.breakpoints() {
+                match condition {
+                    Some(condition) => println!("break {:#05X} if {}", addr, condition),
+                    None => println!("break {:#05X}", addr)
+                }
+            }
+            for (addr, kind) in debugger.watchpoints() {
+                println!("watch {:#05X} {:?}", addr, kind);
+            }
+            return Ok(());
+        },
+
+        "regs" | "r" => {
+            println!("{}", debugger.system().processor.registers().dump());
+            return Ok(());
+        },
+        "mem" | "x" => {
+            let addr = address(args)?;
+            let len = args.get(1).map(|n| number(n)).transpose()?.unwrap_or(16);
+            let bytes = debugger.system().memory.read_many(addr, len as usize).map_err(|err| err.to_string())?;
+            for (i, row) in bytes.chunks(16).enumerate() {
+                let row: Vec<String> = row.iter().map(|byte| format!("{:02X}", byte)).collect();
+                println!("{:04X}:  {}", addr as usize + i * 16, row.join(" "));
+            }
+            return Ok(());
+        },
+        "dis" => {
+            let system = debugger.system();
+            let pc = system.processor.registers().pc();
+            let from = if args.is_empty() { pc } else { address(args)? };
+            let to = (from as usize + 20).min(system.memory.size()) as u16;
+            let data = system.memory.read_range(from, to).map_err(|err| err.to_string())?;
+            print!("{}", Disassembler::new(system.variant).disassemble_linear(&data, from).listing(Some(pc)));
+            return Ok(());
+        },
+        "screen" => {
+            print!("{}", debugger.system().display.dump());
+            return Ok(());
+        },
+        "press" | "release" => {
+            let key = args.first().and_then(|key| u8::from_str_radix(key, 16).ok()).filter(|key| *key < 16)
+                .ok_or_else(|| "expected a key from 0 to F".to_string())?;
+            let keypad = &mut debugger.system_mut().keypad;
+            if command == "press" { keypad.press(key) } else { keypad.release(key) }
+            return Ok(());
+        },
+        "help" | "h" => {
+            print!("{}", HELP);
+            return Ok(());
+        },
+        _ => return Err(format!("unknown command `{}`; try `help`", command))
+    };
+
+    if reason != StopReason::Done {
+        println!("{}", reason);
+    }
+    print_location(debugger);
+    Ok(())
+}
+
+fn print_location(debugger: &Debugger) {
+    let pc = debugger.system().processor.registers().pc();
+    match debugger.operation() {
+        Ok(operation) => println!("{:04X}:  {}", pc, operation),
+        Err(err) => println!("{:04X}:  {}", pc, err)
+    }
+}
+
+fn number(s: &str) -> Result<u16, String> {
+    parse_number(s).ok_or_else(|| format!("invalid number `{}`", s))
+}
+
+fn address(args: &[&str]) -> Result<u16, String> {
+    number(args.first().ok_or_else(|| "missing address".to_string())?)
+}
@@ -0,0 +1,19 @@
+use crate::cli::options::Options;
+use emul8::chip8::Disassembler;
+use emul8::chip8::bios::PROGRAM_ADDR;
+use std::fs;
+
+pub fn disasm(options: &Options) -> Result<(), String> {
+    let path = options.arg(0, "rom")?;
+    let rom = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+
+    let disassembler = Disassembler::new(options.variant);
+    let disassembly = if options.linear {
+        disassembler.disassemble_linear(&rom, PROGRAM_ADDR)
+    } else {
+        disassembler.disassemble(&rom, PROGRAM_ADDR, &[PROGRAM_ADDR])
+    };
+
+    print!("{}", disassembly);
+    Ok(())
+}
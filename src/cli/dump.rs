@@ -0,0 +1,25 @@
+use crate::cli::options::Options;
+
+pub fn dump(options: &Options) -> Result<(), String> {
+    let (mut system, mut movie) = options.boot_with_movie()?;
+
+    let mut result = Ok(());
+    for _ in 0..options.frames.or_else(|| movie.frames()).unwrap_or(0) {
+        if !system.is_running() {
+            break;
+        }
+        movie.frame(&mut system)?;
+        if let Err(err) = system.run_frame() {
+            result = Err(err.to_string());
+            break;
+        }
+    }
+
+    println!("state:   {:?}", system.state());
+    println!("frames:  {}\n", system.timers.frames());
+    println!("{}", system.processor.registers().dump());
+    println!("{}", system.display.dump());
+    print!("{}", system.memory.dump());
+    options.finish(&mut system, &mut movie)?;
+    result
+}
\ No newline at end of file
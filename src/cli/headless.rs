@@ -0,0 +1,75 @@
+use crate::cli::options::{MovieMode, Options};
+use emul8::chip8::Chip8;
+use emul8::chip8::headless::{Report, Script, Stop, Until};
+use std::fmt::Write;
+
+pub fn headless(options: &Options) -> Result<(), String> {
+    let mut script = Script::new();
+    script.until.extend(options.frames.map(Until::Frames));
+    script.until.extend(options.cycles.map(Until::Cycles));
+    script.until.extend(options.until_pc.map(Until::Pc));
+    script.until.extend(options.until_memory.iter().copied().map(Until::Memory));
+    script.keys = options.keys.clone();
+    if script.until.is_empty() {
+        return Err("expected a condition to stop at, e.g. `--frames` or `--until-pc`".to_string());
+    }
+
+    let mut system = options.boot()?;
+    let report = system.run_script(&script);
+    println!("{}", json(&system, &report));
+    options.finish(&mut system, &mut MovieMode::Off)
+}
+
+/// Formats the report of a run and the state `system` was left in as a JSON object, the display as
+/// a row of colour digits per line and memory as a string of hexadecimal digits.
+fn json(system: &Chip8, report: &Report) -> String {
+    let kind = match report.stop {
+        Stop::Until(_) => "until",
+        Stop::Stopped(_) => "stopped",
+        Stop::Faulted(_) => "faulted"
+    };
+    let registers = system.processor.registers();
+    let v: Vec<String> = (0..16).map(|x| registers.read_v(x).to_string()).collect();
+    let stack: Vec<String> = registers.stack().iter().map(u16::to_string).collect();
+    let display = &system.display;
+    let rows: Vec<String> = (0..display.height())
+        .map(|y| format!("\"{}\"", (0..display.width()).map(|x| char::from(b'0' + display.color(x, y))).collect::<String>()))
+        .collect();
+    let memory = system.memory.read_many(0, system.memory.size()).unwrap_or_default();
+    let memory: String = memory.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    let mut out = String::new();
+    writeln!(out, "{{").unwrap();
+    writeln!(out, "  \"stop\": {{ \"kind\": \"{}\", \"message\": {} }},", kind, string(&report.stop.to_string())).unwrap();
+    writeln!(out, "  \"frames\": {},", report.frames).unwrap();
+    writeln!(out, "  \"cycles\": {},", report.cycles).unwrap();
+    writeln!(out, "  \"registers\": {{").unwrap();
+    writeln!(out, "    \"pc\": {}, \"i\": {}, \"sp\": {}, \"dt\": {}, \"st\": {},", registers.pc(), registers.i(),
+        registers.sp(), registers.delay_timer(), registers.sound_timer()).unwrap();
+    writeln!(out, "    \"v\": [{}],", v.join(", ")).unwrap();
+    writeln!(out, "    \"stack\": [{}]", stack.join(", ")).unwrap();
+    writeln!(out, "  }},").unwrap();
+    writeln!(out, "  \"display\": {{").unwrap();
+    writeln!(out, "    \"width\": {}, \"height\": {},", display.width(), display.height()).unwrap();
+    writeln!(out, "    \"rows\": [\n      {}\n    ]", rows.join(",\n      ")).unwrap();
+    writeln!(out, "  }},").unwrap();
+    writeln!(out, "  \"memory\": \"{}\"", memory).unwrap();
+    write!(out, "}}").unwrap();
+    out
+}
+
+/// Quotes `s` as a JSON string.
+fn string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
\ No newline at end of file
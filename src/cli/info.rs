@@ -0,0 +1,30 @@
+use crate::cli::options::Options;
+use emul8::chip8::{Opcode, Variant};
+use emul8::chip8::bios::PROGRAM_ADDR;
+use std::fs;
+
+pub fn info(options: &Options) -> Result<(), String> {
+    let path = options.arg(0, "rom")?;
+    let rom = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+
+    println!("{:<10}{}", "file", path);
+    println!("{:<10}{1} (0x{1:X}) bytes", "size", rom.len());
+
+    // Code and data can't be told apart here, so this is only a hint: any word that decodes to an
+    // instruction of an extension counts.
+    let variant = [Variant::Chip8, Variant::SuperChip, Variant::XoChip].iter()
+        .find(|variant| rom.chunks_exact(2).all(|word| {
+            variant.supports(&Opcode::from(u16::from_be_bytes([word[0], word[1]])))
+        }))
+        .copied()
+        .unwrap_or(Variant::XoChip);
+    println!("{:<10}{:?}", "variant", variant);
+
+    for variant in &[Variant::Chip8, Variant::SuperChip, Variant::XoChip] {
+        let capacity = variant.memory_size() - PROGRAM_ADDR as usize;
+        println!("{:<10}{:?}: {} of {} bytes ({})", "fits", variant, rom.len(), capacity,
+                 if rom.len() <= capacity { "yes" } else { "no" });
+    }
+
+    Ok(())
+}
\ No newline at end of file
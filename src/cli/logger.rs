@@ -0,0 +1,60 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Writes the log records of the levels enabled for their targets to the standard error.
+pub struct Logger {
+    /// Level of the records logged for targets without a level of their own.
+    level: LevelFilter,
+    /// Levels of the records logged for targets, and the targets nested under them.
+    targets: Vec<(String, LevelFilter)>
+}
+impl Logger {
+    /// Parses a comma-separated list of levels for targets, and the level for the others, e.g.
+    /// `warn,emul8::cpu=trace`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut logger = Self {
+            level: LevelFilter::Off,
+            targets: Vec::new()
+        };
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (target, level) = match item.split_once('=') {
+                Some((target, level)) => (Some(target), level),
+                None => (None, item)
+            };
+            let level = level.parse().map_err(|_| format!("unknown log level `{}`", level))?;
+            match target {
+                Some(target) => logger.targets.push((target.to_string(), level)),
+                None => logger.level = level
+            }
+        }
+        Ok(logger)
+    }
+
+    /// Makes this the logger of the process.
+    pub fn install(self) -> Result<(), String> {
+        let max = self.targets.iter().map(|(_, level)| *level).fold(self.level, Ord::max);
+        log::set_logger(Box::leak(Box::new(self))).map_err(|err| err.to_string())?;
+        log::set_max_level(max);
+        Ok(())
+    }
+
+    /// The level enabled for `target`: that of the most specific target it is nested under.
+    fn level(&self, target: &str) -> LevelFilter {
+        self.targets.iter()
+            .filter(|(prefix, _)| target == prefix || target.starts_with(&format!("{}::", prefix)))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{:<5} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) { }
+}
\ No newline at end of file
@@ -0,0 +1,132 @@
+mod options;
+mod logger;
+mod run;
+mod disasm;
+mod info;
+mod dump;
+mod asm;
+mod debug;
+mod term;
+mod headless;
+
+use self::logger::Logger;
+use self::options::Options;
+
+const USAGE: &str = "\
+usage: emul8 <command> [options] <args>
+
+commands:
+  run <rom>       run a ROM in real time, printing the screen when it stops
+  disasm <rom>    list the instructions and data of a ROM
+  info <rom>      show the size of a ROM and the variant it appears to target
+  dump <rom>      print the registers, screen and memory after running a ROM
+  asm <src> <rom> assemble an Octo source file into a ROM, printing its symbol table
+  debug <rom>     step through a ROM with breakpoints and watchpoints (`help` for commands)
+  term <rom>      run a ROM in the terminal, with the keys 1-4, Q-R, A-F and Z-V as the keypad,
+                  until Escape or Ctrl-C
+  headless <rom>  run a ROM without a display until a condition, printing the report, registers,
+                  screen and memory as JSON
+
+options:
+  --variant <chip8|schip|xochip>  instruction set to emulate or assemble for (default: chip8)
+  --quirks <list>                 comma-separated quirk presets (vip, chip48, schip, xochip)
+                                  and quirks (shift-vy, load-store, load-store-x, jump-vx,
+                                  vf-reset, clip, display-wait), each negated with a `no-`
+                                  prefix (default: the usual quirks of the variant)
+  --clock <hz>                    instructions executed per second (default: 600)
+  --seed <n>                      seed for the random number generator (default: random)
+  --frames <n>                    number of 60 Hz frames to run for (default: run, until the
+                                  program exits; dump: 0)
+  --cycles <n>                    headless: stop after n cycles
+  --until-pc <addr>               headless: stop when the program counter reaches an address
+  --until-mem <condition>         headless: stop when a byte of memory compares to a value,
+                                  e.g. `[0x300] == 5`; repeatable
+  --press <frame>:<key>           headless: press a key of the keypad at a frame of the run;
+                                  repeatable
+  --release <frame>:<key>         headless: release a key at a frame of the run; repeatable
+  --screenshot <file>             save the screen when the run stops, as a PNG, PBM, PGM or text
+                                  image by the extension of the file (.png, .pbm, .pgm, .txt)
+  --screenshot-frame <n>          save the screenshot after n frames since power-on instead
+  --animation <file>              record the screen at every frame to an animated GIF, or PNG
+                                  for the .png and .apng extensions; GIFs show frames for 2/100 s
+                                  at least, replacing shorter ones by the next
+  --scale <n>                     size in pixels of the pixels of images (default: 1)
+  --colors <off>,<on>             colours of the pixels off and on in images, as RRGGBB
+                                  (default: 000000,FFFFFF)
+  --glyphs <block|braille>        term: draw the display with half blocks, in colour, or with
+                                  braille patterns, in half the width (default: block)
+  --panel                         term: show the registers next to the display
+  --linear                        disasm: list every word as an instruction, instead of
+                                  following the flow of execution from the entry point
+  --load-state <file>             run, dump, debug, headless: start from a save state instead of a ROM,
+                                  which can then be left out
+  --save-state <file>             run, dump, debug, headless: save the state of the machine when it stops
+  --record <file>                 run, dump: record the keys pressed during the run to a movie,
+                                  along with the seed (random if not given) and the rest of the
+                                  machine, to reproduce the run exactly
+  --play <file>                   run, dump: play a movie back on the ROM it was recorded with,
+                                  taking the variant, quirks, clock and seed from it, and stopping
+                                  with an error if the run doesn't turn out the same
+  --trace <file>                  run, dump, debug: write a line for every instruction executed,
+                                  with the cycle, address, instruction and registers
+  --trace-range <first>-<last>    only trace the instructions at addresses in this range
+  --trace-from <cycle>            only trace the instructions from this cycle on
+  --trace-count <n>               stop tracing after n instructions
+  --log <levels>                  write diagnostics to the standard error, at a level (off, error,
+                                  warn, info, debug, trace) and levels for targets, e.g.
+                                  `warn,emul8::cpu=trace` (default: off)
+";
+
+/// Runs the command line `args` (excluding the program name), returning the exit code.
+pub fn main(args: &[String]) -> i32 {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => {
+            eprint!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return 2;
+        }
+    };
+
+    if let Some(spec) = &options.log {
+        if let Err(err) = Logger::parse(spec).and_then(Logger::install) {
+            eprintln!("error: {}", err);
+            return 2;
+        }
+    }
+
+    let result = match command {
+        "run" => run::run(&options),
+        "disasm" => disasm::disasm(&options),
+        "info" => info::info(&options),
+        "dump" => dump::dump(&options),
+        "asm" => asm::asm(&options),
+        "debug" => debug::debug(&options),
+        "term" => term::term(&options),
+        "headless" => headless::headless(&options),
+        "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        },
+        _ => {
+            eprintln!("error: unknown command `{}`\n", command);
+            eprint!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            1
+        }
+    }
+}
\ No newline at end of file
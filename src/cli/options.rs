@@ -0,0 +1,351 @@
+use emul8::chip8::{Camera, Chip8, Movie, Quirks, Recorder, Screenshot, SplitMix64, Tracer, Variant};
+use emul8::chip8::headless::{KeyEvent, MemoryCondition};
+use emul8::chip8::screenshot::{parse_color, Color};
+use emul8::chip8::terminal::Glyphs;
+use emul8::chip8::timer::DEFAULT_CLOCK_SPEED;
+use std::convert::TryFrom;
+use std::fs;
+use std::ops::RangeInclusive;
+
+/// The options shared by every command, along with its positional arguments.
+pub struct Options {
+    pub variant: Variant,
+    /// Quirks to run with; the usual quirks of the variant if not given.
+    pub quirks: Option<Quirks>,
+    /// Instructions executed per second of emulated time.
+    pub clock_speed: u32,
+    pub seed: Option<u64>,
+    /// Number of frames to run for; unbounded if not given.
+    pub frames: Option<u64>,
+    /// Number of cycles to run for; unbounded if not given.
+    pub cycles: Option<u64>,
+    /// Address to run until the program counter reaches.
+    pub until_pc: Option<u16>,
+    /// Conditions on memory to run until any holds.
+    pub until_memory: Vec<MemoryCondition>,
+    /// Keys to press and release at given frames.
+    pub keys: Vec<KeyEvent>,
+    /// Disassemble every word as an instruction, instead of following the flow of execution.
+    pub linear: bool,
+    /// Save state to start from, instead of a freshly loaded ROM.
+    pub load_state: Option<String>,
+    /// File to save the state of the machine to when it stops.
+    pub save_state: Option<String>,
+    /// Movie file to record the run to.
+    pub record: Option<String>,
+    /// Movie file to play the run back from.
+    pub play: Option<String>,
+    /// File to trace the instructions executed to.
+    pub trace: Option<String>,
+    /// Addresses of the instructions traced; all of them if not given.
+    pub trace_range: Option<RangeInclusive<u16>>,
+    /// Cycle to start tracing from.
+    pub trace_from: u64,
+    /// Number of instructions traced at most; unbounded if not given.
+    pub trace_count: Option<u64>,
+    /// Levels of the diagnostics logged, as parsed by `Logger`.
+    pub log: Option<String>,
+    /// File to save a screenshot to.
+    pub screenshot: Option<String>,
+    /// Frame after which the screenshot is taken; when the run stops if not given.
+    pub screenshot_frame: Option<u64>,
+    /// File to record the display to as an animation.
+    pub animation: Option<String>,
+    /// Size in pixels of the pixels of images.
+    pub scale: usize,
+    /// Colours of the pixels off and on in images; the default ones if not given.
+    pub colors: Option<(Color, Color)>,
+    /// Characters the terminal front-end draws the display with.
+    pub glyphs: Glyphs,
+    /// Show the registers next to the display in the terminal front-end.
+    pub panel: bool,
+    pub args: Vec<String>
+}
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self {
+            variant: Variant::default(),
+            quirks: None,
+            clock_speed: DEFAULT_CLOCK_SPEED,
+            seed: None,
+            frames: None,
+            cycles: None,
+            until_pc: None,
+            until_memory: Vec::new(),
+            keys: Vec::new(),
+            linear: false,
+            load_state: None,
+            save_state: None,
+            record: None,
+            play: None,
+            trace: None,
+            trace_range: None,
+            trace_from: 0,
+            trace_count: None,
+            log: None,
+            screenshot: None,
+            screenshot_frame: None,
+            animation: None,
+            scale: 1,
+            colors: None,
+            glyphs: Glyphs::default(),
+            panel: false,
+            args: Vec::new()
+        };
+        let mut quirks = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                options.args.push(arg.clone());
+                continue;
+            }
+
+            let (name, inline) = match arg.find('=') {
+                Some(i) => (&arg[2..i], Some(arg[(i + 1)..].to_string())),
+                None => (&arg[2..], None)
+            };
+            let mut value = || inline.clone().or_else(|| args.next().cloned())
+                .ok_or_else(|| format!("missing value for `--{}`", name));
+
+            match name {
+                "linear" => options.linear = true,
+                "panel" => options.panel = true,
+                "variant" => options.variant = value()?.parse()?,
+                "quirks" => quirks = Some(value()?),
+                "clock" => options.clock_speed = parse_number(name, &value()?)?,
+                "seed" => options.seed = Some(parse_number(name, &value()?)?),
+                "frames" => options.frames = Some(parse_number(name, &value()?)?),
+                "cycles" => options.cycles = Some(parse_number(name, &value()?)?),
+                "until-pc" => options.until_pc = Some(parse_number(name, &value()?)?),
+                "until-mem" => options.until_memory.push(value()?.parse()?),
+                "press" | "release" => options.keys.push(parse_key_event(name, &value()?)?),
+                "load-state" => options.load_state = Some(value()?),
+                "save-state" => options.save_state = Some(value()?),
+                "record" => options.record = Some(value()?),
+                "play" => options.play = Some(value()?),
+                "trace" => options.trace = Some(value()?),
+                "trace-range" => options.trace_range = Some(parse_range(name, &value()?)?),
+                "trace-from" => options.trace_from = parse_number(name, &value()?)?,
+                "trace-count" => options.trace_count = Some(parse_number(name, &value()?)?),
+                "log" => options.log = Some(value()?),
+                "screenshot" => options.screenshot = Some(value()?),
+                "screenshot-frame" => options.screenshot_frame = Some(parse_number(name, &value()?)?),
+                "animation" => options.animation = Some(value()?),
+                "scale" => options.scale = parse_number(name, &value()?)?,
+                "colors" => options.colors = Some(parse_colors(name, &value()?)?),
+                "glyphs" => options.glyphs = value()?.parse()?,
+                _ => return Err(format!("unknown option `--{}`", name))
+            }
+        }
+
+        // The quirks start out from the variant's, so they can only be parsed once it is known.
+        if let Some(quirks) = quirks {
+            options.quirks = Some(options.variant.quirks().apply_list(&quirks)?);
+        }
+
+        Ok(options)
+    }
+
+    pub fn arg(&self, i: usize, name: &str) -> Result<&str, String> {
+        self.args.get(i).map(|arg| arg.as_str()).ok_or_else(|| format!("missing argument <{}>", name))
+    }
+
+    /// Creates and initialises a machine as configured by the options.
+    pub fn machine(&self) -> Result<Chip8, String> {
+        let mut system = Chip8::with_variant(self.variant, self.quirks.unwrap_or_else(|| self.variant.quirks()));
+        system.timers.set_clock_speed(self.clock_speed);
+        if let Some(seed) = self.seed {
+            system.seed(seed);
+        }
+        system.init().map_err(|err| err.to_string())?;
+        self.attach(&mut system)?;
+        Ok(system)
+    }
+    /// Creates a machine with the ROM at `path` loaded.
+    pub fn machine_with_rom(&self, path: &str) -> Result<Chip8, String> {
+        let mut system = self.machine()?;
+        system.bios.load_rom_file(&mut system.memory, path).map_err(|err| format!("{}: {}", path, err))?;
+        Ok(system)
+    }
+    /// Creates the machine to run: restored from `--load-state` if given, or else with the ROM
+    /// given as the first argument.
+    pub fn boot(&self) -> Result<Chip8, String> {
+        match &self.load_state {
+            Some(path) => {
+                let mut system = self.machine()?;
+                system.load_state_file(path).map_err(|err| format!("{}: {}", path, err))?;
+                Ok(system)
+            },
+            None => self.machine_with_rom(self.arg(0, "rom")?)
+        }
+    }
+    /// Creates the machine to run along with its movie: the machine recorded in `--play` if given,
+    /// or else the one `boot` creates, recorded to `--record` if given.
+    pub fn boot_with_movie(&self) -> Result<(Chip8, MovieMode), String> {
+        if self.play.is_some() && self.record.is_some() {
+            return Err("`--play` and `--record` can't be combined".to_string());
+        }
+        if (self.play.is_some() || self.record.is_some()) && self.load_state.is_some() {
+            return Err("movies start from a ROM, and can't be combined with `--load-state`".to_string());
+        }
+
+        match (&self.play, &self.record) {
+            (Some(path), _) => {
+                let movie = Movie::load_file(path).map_err(|err| format!("{}: {}", path, err))?;
+                let mut system = movie.boot(&self.rom()?).map_err(|err| err.to_string())?;
+                self.attach(&mut system)?;
+                Ok((system, MovieMode::Play(movie)))
+            },
+            (_, Some(_)) => {
+                let rom = self.rom()?;
+                let quirks = self.quirks.unwrap_or_else(|| self.variant.quirks());
+                let seed = self.seed.unwrap_or_else(|| SplitMix64::from_entropy().next_u64());
+                let movie = Movie::new(self.variant, quirks, self.clock_speed, seed, &rom);
+                let mut system = movie.boot(&rom).map_err(|err| err.to_string())?;
+                self.attach(&mut system)?;
+                Ok((system, MovieMode::Record(movie)))
+            },
+            (None, None) => Ok((self.boot()?, MovieMode::Off))
+        }
+    }
+    /// Attaches a tracer to `system`, writing to `--trace` if given, a camera saving to
+    /// `--screenshot` if given, and a recorder saving to `--animation` if given.
+    fn attach(&self, system: &mut Chip8) -> Result<(), String> {
+        if let Some(path) = &self.trace {
+            let mut tracer = Tracer::to_file(path).map_err(|err| format!("{}: {}", path, err))?;
+            if let Some(range) = &self.trace_range {
+                tracer.set_range(range.clone());
+            }
+            tracer.set_start(self.trace_from);
+            tracer.set_limit(self.trace_count);
+            system.tracer = Some(tracer);
+        }
+        if let Some(path) = &self.screenshot {
+            let mut camera = Camera::new(self.screenshot_style(), path).map_err(|err| format!("{}: {}", path, err))?;
+            camera.set_frame(self.screenshot_frame);
+            system.camera = Some(camera);
+        }
+        if let Some(path) = &self.animation {
+            system.recorder = Some(Recorder::new(self.screenshot_style(), path).map_err(|err| format!("{}: {}", path, err))?);
+        }
+        Ok(())
+    }
+    /// The scale and colours of the images, from `--scale` and `--colors`.
+    pub fn screenshot_style(&self) -> Screenshot {
+        let mut screenshot = Screenshot::new();
+        screenshot.set_scale(self.scale);
+        if let Some((off, on)) = self.colors {
+            screenshot.set_colors(off, on);
+        }
+        screenshot
+    }
+    /// Reads the ROM given as the first argument.
+    fn rom(&self) -> Result<Vec<u8>, String> {
+        let path = self.arg(0, "rom")?;
+        fs::read(path).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Wraps up a run that left `system` as it is: saves its state, ends its movie, flushes its
+    /// trace, takes its screenshot and saves its animation.
+    pub fn finish(&self, system: &mut Chip8, movie: &mut MovieMode) -> Result<(), String> {
+        self.save_state(system)?;
+        self.finish_movie(movie, system)?;
+        if let (Some(tracer), Some(path)) = (system.tracer.take(), &self.trace) {
+            tracer.finish().map_err(|err| format!("{}: {}", path, err))?;
+        }
+        if let (Some(camera), Some(path)) = (system.camera.take(), &self.screenshot) {
+            camera.finish(system).map_err(|err| format!("{}: {}", path, err))?;
+        }
+        match (system.recorder.take(), &self.animation) {
+            (Some(recorder), Some(path)) => recorder.finish(system).map_err(|err| format!("{}: {}", path, err)),
+            _ => Ok(())
+        }
+    }
+    /// Saves the state of `system` to `--save-state`, if given.
+    fn save_state(&self, system: &Chip8) -> Result<(), String> {
+        match &self.save_state {
+            Some(path) => system.save_state_file(path).map_err(|err| format!("{}: {}", path, err)),
+            None => Ok(())
+        }
+    }
+    /// Ends the movie of a run that left `system` as it is: saves the recording to `--record`, or
+    /// checks that the playback ended like the recording did, unless `--frames` cut it short.
+    fn finish_movie(&self, movie: &mut MovieMode, system: &Chip8) -> Result<(), String> {
+        match movie {
+            MovieMode::Off => Ok(()),
+            MovieMode::Record(movie) => {
+                movie.finish_recording(system);
+                let path = self.record.as_deref().unwrap_or_default();
+                movie.save_file(path).map_err(|err| format!("{}: {}", path, err))
+            },
+            MovieMode::Play(movie) if self.frames.map_or(true, |frames| frames >= movie.frames()) =>
+                movie.finish_playback(system).map_err(|err| err.to_string()),
+            MovieMode::Play(_) => Ok(())
+        }
+    }
+}
+
+/// What a run does with its input.
+pub enum MovieMode {
+    /// The input is neither recorded nor played back.
+    Off,
+    Record(Movie),
+    Play(Movie)
+}
+impl MovieMode {
+    /// Number of frames to run for, if set by a movie being played back.
+    pub fn frames(&self) -> Option<u64> {
+        match self {
+            MovieMode::Play(movie) => Some(movie.frames()),
+            _ => None
+        }
+    }
+
+    /// Records, or plays back the keys held during the frame `system` is about to run.
+    pub fn frame(&mut self, system: &mut Chip8) -> Result<(), String> {
+        match self {
+            MovieMode::Off => Ok(()),
+            MovieMode::Record(movie) => {
+                movie.record(system);
+                Ok(())
+            },
+            MovieMode::Play(movie) => movie.play(system).map_err(|err| err.to_string())
+        }
+    }
+}
+
+/// Parses a range of addresses, given by its first and last address separated by a `-`.
+pub fn parse_range(name: &str, value: &str) -> Result<RangeInclusive<u16>, String> {
+    match value.split_once('-') {
+        Some((first, last)) => Ok(parse_number(name, first)?..=parse_number(name, last)?),
+        None => Err(format!("invalid range `{}` for `--{}`, expected <first>-<last>", value, name))
+    }
+}
+
+/// Parses the colours of the pixels off and on, separated by a `,`.
+pub fn parse_colors(name: &str, value: &str) -> Result<(Color, Color), String> {
+    match value.split_once(',') {
+        Some((off, on)) => Ok((parse_color(off)?, parse_color(on)?)),
+        None => Err(format!("invalid colours `{}` for `--{}`, expected <off>,<on>", value, name))
+    }
+}
+
+/// Parses `<frame>:<key>`, the key in hexadecimal, pressed or released as `name` says.
+pub fn parse_key_event(name: &str, value: &str) -> Result<KeyEvent, String> {
+    let (frame, key) = value.split_once(':')
+        .ok_or_else(|| format!("invalid value `{}` for `--{}`, expected <frame>:<key>", value, name))?;
+    let key = u8::from_str_radix(key, 16).ok().filter(|key| *key < 16)
+        .ok_or_else(|| format!("invalid key `{}` for `--{}`, expected 0-F", key, name))?;
+    Ok(KeyEvent { frame: parse_number(name, frame)?, key, pressed: name == "press" })
+}
+
+/// Parses a decimal, or `0x` prefixed hexadecimal number.
+pub fn parse_number<T: TryFrom<u64>>(name: &str, value: &str) -> Result<T, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse()
+    };
+    parsed.ok().and_then(|n| T::try_from(n).ok())
+        .ok_or_else(|| format!("invalid value `{}` for `--{}`", value, name))
+}
\ No newline at end of file
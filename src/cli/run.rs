@@ -0,0 +1,30 @@
+use crate::cli::options::Options;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let (mut system, mut movie) = options.boot_with_movie()?;
+    let limit = options.frames.or_else(|| movie.frames());
+    let frame_time = Duration::from_secs(1) / 60;
+
+    // A fault still ends the run normally, so that its movie can be used to reproduce it.
+    let mut result = Ok(());
+    let mut frames = 0;
+    while system.is_running() && limit.map_or(true, |limit| frames < limit) {
+        let start = Instant::now();
+        movie.frame(&mut system)?;
+        if let Err(err) = system.run_frame() {
+            result = Err(err.to_string());
+            break;
+        }
+        frames += 1;
+
+        if let Some(rest) = frame_time.checked_sub(start.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+
+    print!("{}", system.display.dump());
+    options.finish(&mut system, &mut movie)?;
+    result
+}
\ No newline at end of file
@@ -0,0 +1,140 @@
+use crate::cli::options::{MovieMode, Options};
+use emul8::chip8::TerminalRenderer;
+use emul8::chip8::terminal::keypad_key;
+use log::warn;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of frames a key stays pressed after the terminal last reported it. Terminals only
+/// report key presses, repeated while a key is held, so releases are guessed from them stopping.
+///
+/// A held key is reported once, then again only after the keyboard's auto-repeat delay, commonly
+/// 250 to 600 ms, so holding must outlast it or the key would flicker off before repeating. The
+/// cost is that releases are noticed about 2/3 of a second late.
+const HOLD_FRAMES: u64 = 40;
+
+/// Ctrl-C, which raw mode delivers as input instead of a signal; quits, along with Escape.
+const INTERRUPT: u8 = 0x03;
+const ESCAPE: u8 = 0x1B;
+
+pub fn term(options: &Options) -> Result<(), String> {
+    let (mut system, mut movie) = options.boot_with_movie()?;
+    let limit = options.frames.or_else(|| movie.frames());
+    let frame_time = Duration::from_secs(1) / 60;
+
+    let mut renderer = TerminalRenderer::new(options.glyphs);
+    renderer.set_panel(options.panel);
+    let terminal = Terminal::enter();
+    let input = terminal.raw.then(read_input);
+
+    // The frame each key of the keypad was last reported at, while pressed.
+    let mut pressed = [None; 16];
+    let mut result = Ok(());
+    let mut frames = 0;
+    'run: while system.is_running() && limit.map_or(true, |limit| frames < limit) {
+        let start = Instant::now();
+        let frame = system.timers.frames();
+
+        let bytes: Vec<u8> = input.iter().flat_map(Receiver::try_iter).collect();
+        for (i, byte) in bytes.iter().copied().enumerate() {
+            match byte {
+                INTERRUPT => break 'run,
+                // Escape on its own, and not starting the sequence of a special key.
+                ESCAPE if i + 1 == bytes.len() => break 'run,
+                ESCAPE => break,
+                // A movie played back has the keypad to itself.
+                _ if matches!(movie, MovieMode::Play(_)) => { },
+                _ => if let Some(key) = keypad_key(byte as char) {
+                    system.keypad.press(key);
+                    pressed[key as usize] = Some(frame);
+                }
+            }
+        }
+        for (key, since) in pressed.iter_mut().enumerate() {
+            if since.is_some_and(|since| frame >= since + HOLD_FRAMES) {
+                system.keypad.release(key as u8);
+                *since = None;
+            }
+        }
+
+        if let Err(err) = movie.frame(&mut system) {
+            result = Err(err);
+            break;
+        }
+        if let Err(err) = system.run_frame() {
+            result = Err(err.to_string());
+            break;
+        }
+        frames += 1;
+
+        terminal.write(&renderer.render(&system));
+        if let Some(rest) = frame_time.checked_sub(start.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+
+    drop(terminal);
+    options.finish(&mut system, &mut movie)?;
+    result
+}
+
+/// Reads the standard input on a thread of its own, so that it can be polled once per frame.
+fn read_input() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for byte in io::stdin().lock().bytes() {
+            match byte {
+                Ok(byte) if sender.send(byte).is_ok() => { },
+                _ => break
+            }
+        }
+    });
+    receiver
+}
+
+/// The terminal switched to a screen of its own, hiding the cursor, and to raw mode if the
+/// standard input is a terminal; restored as it was when dropped.
+struct Terminal {
+    /// Whether the terminal is in raw mode, so that keys are read as they are pressed.
+    raw: bool,
+    /// The settings of the terminal to restore, as printed by `stty -g`.
+    settings: Option<String>
+}
+impl Terminal {
+    fn enter() -> Self {
+        let mut terminal = Self { raw: false, settings: None };
+        if io::stdin().is_terminal() {
+            terminal.settings = stty(&["-g"]).map(|settings| settings.trim().to_string());
+            terminal.raw = terminal.settings.is_some() && stty(&["raw", "-echo"]).is_some();
+        }
+        if !terminal.raw {
+            warn!(target: "emul8::term", "the standard input is not a terminal; the keyboard is ignored");
+        }
+
+        terminal.write("\x1b[?1049h\x1b[?25l");
+        terminal
+    }
+
+    fn write(&self, text: &str) {
+        let mut stdout = io::stdout().lock();
+        // There is nowhere left to report a terminal that can't be written to.
+        let _ = stdout.write_all(text.as_bytes()).and_then(|_| stdout.flush());
+    }
+}
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        self.write("\x1b[0m\x1b[?25h\x1b[?1049l");
+        if let Some(settings) = &self.settings {
+            stty(&[settings]);
+        }
+    }
+}
+
+/// Runs `stty` on the terminal of the standard input, returning its output if it succeeded.
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output().ok()?;
+    if output.status.success() { String::from_utf8(output.stdout).ok() } else { None }
+}
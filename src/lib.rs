@@ -1 +1,9 @@
-pub mod chip8;
\ No newline at end of file
+//! A general-purpose machine emulation platform.
+//!
+//! The [`chip8`] module emulates CHIP-8, along with its SUPER-CHIP and XO-CHIP extensions.
+//!
+//! Nothing is written to the standard output; diagnostics go through the [`log`] facade, under
+//! the targets `emul8::cpu` for the execution of instructions, and `emul8::mem` for the loading
+//! of programs and state into memory. Every instruction executed is logged at the trace level.
+
+pub mod chip8;
@@ -1,3 +1,9 @@
+mod cli;
+
+use std::env;
+use std::process;
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().skip(1).collect();
+    process::exit(cli::main(&args));
 }